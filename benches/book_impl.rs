@@ -0,0 +1,63 @@
+//! Times the same order flow through a `MatchingEngine` on each `Book` backend:
+//! `cargo bench --bench book_impl`.
+
+use std::time::Instant;
+
+use rusty_matching_engine::book::{BTreeBook, Book, HeapBook};
+use rusty_matching_engine::orders::Qty;
+use rusty_matching_engine::{MatchingEngine, Order, Side};
+
+const ORDERS: u64 = 50_000;
+
+fn flow() -> Vec<Order> {
+    // deterministic prices wandering around 1000, with every fourth order
+    // cancelling an earlier one
+    (0..ORDERS)
+        .map(|i| {
+            let side = if i % 2 == 0 { Side::Bid } else { Side::Ask };
+            let offset = ((i * 7919) % 21) as i64 - 10;
+            let mut order = Order::limit(
+                side,
+                Qty::units(1 + (i % 5) as i64),
+                1000 + offset,
+                i as i32,
+            );
+            order.id = i + 1;
+            order
+        })
+        .collect()
+}
+
+fn run<B: Book>(orders: &[Order]) -> usize {
+    let mut engine = MatchingEngine::<B>::with_books();
+    let mut fills = 0;
+    for order in orders {
+        fills += engine.submit(*order).trades.len();
+        if order.id % 4 == 0 {
+            // the earlier order may have filled already
+            let _ = engine.cancel(order.id / 2);
+        }
+        if order.id % 100 == 0 {
+            engine.depth(10);
+        }
+    }
+    fills
+}
+
+fn bench<B: Book>(name: &str, orders: &[Order]) {
+    let start = Instant::now();
+    let fills = run::<B>(orders);
+    let elapsed = start.elapsed();
+    println!(
+        "{:>8}: {:>8} ns/order ({} fills)",
+        name,
+        elapsed.as_nanos() / orders.len() as u128,
+        fills
+    );
+}
+
+fn main() {
+    let orders = flow();
+    bench::<HeapBook>("heap", &orders);
+    bench::<BTreeBook>("btreemap", &orders);
+}
@@ -0,0 +1,190 @@
+use tokio::sync::{broadcast, mpsc, oneshot};
+
+use crate::engine::{ExecutionReport, MatchingEngine, OrderStatus};
+use crate::orders::{Order, PxTicks, Qty, Trade};
+
+enum Command {
+    Submit(Order, oneshot::Sender<ExecutionReport>),
+    Cancel(u64, oneshot::Sender<ExecutionReport>),
+    Amend(u64, Qty, PxTicks, oneshot::Sender<ExecutionReport>),
+}
+
+/// The engine task has stopped, so the command couldn't be processed.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct EngineClosed;
+
+/// Owns a `MatchingEngine` inside a single task. Commands arrive over an mpsc
+/// channel from `EngineHandle`s and every trade is broadcast to subscribers.
+pub struct AsyncEngine {
+    engine: MatchingEngine,
+    commands: mpsc::Receiver<Command>,
+    trades: broadcast::Sender<Trade>,
+}
+
+#[derive(Clone)]
+pub struct EngineHandle {
+    commands: mpsc::Sender<Command>,
+    trades: broadcast::Sender<Trade>,
+}
+
+impl AsyncEngine {
+    pub fn new(engine: MatchingEngine, capacity: usize) -> (AsyncEngine, EngineHandle) {
+        let (command_sender, commands) = mpsc::channel(capacity);
+        let (trades, _) = broadcast::channel(capacity);
+        let handle = EngineHandle {
+            commands: command_sender,
+            trades: trades.clone(),
+        };
+        (
+            AsyncEngine {
+                engine,
+                commands,
+                trades,
+            },
+            handle,
+        )
+    }
+
+    /// Processes commands until every handle is dropped, then hands the engine back.
+    pub async fn run(mut self) -> MatchingEngine {
+        while let Some(command) = self.commands.recv().await {
+            let tape_len = self.engine.trades().len();
+            let (report, respond_to) = self.execute(command);
+            for trade in &self.engine.trades()[tape_len..] {
+                // no subscribers is fine
+                let _ = self.trades.send(*trade);
+            }
+            let _ = respond_to.send(report);
+        }
+        self.engine
+    }
+
+    fn execute(&mut self, command: Command) -> (ExecutionReport, oneshot::Sender<ExecutionReport>) {
+        match command {
+            Command::Submit(order, respond_to) => (self.engine.submit(order), respond_to),
+            Command::Cancel(id, respond_to) => {
+                let status = match self.engine.cancel(id) {
+                    Ok(_) => OrderStatus::Cancelled,
+                    Err(reason) => OrderStatus::Rejected(reason),
+                };
+                (report(id, status), respond_to)
+            }
+            Command::Amend(id, amount, price, respond_to) => {
+                let report = self
+                    .engine
+                    .amend(id, amount, price)
+                    .unwrap_or_else(|reason| report(id, OrderStatus::Rejected(reason)));
+                (report, respond_to)
+            }
+        }
+    }
+}
+
+impl EngineHandle {
+    pub async fn submit(&self, order: Order) -> Result<ExecutionReport, EngineClosed> {
+        self.request(|respond_to| Command::Submit(order, respond_to))
+            .await
+    }
+
+    pub async fn cancel(&self, id: u64) -> Result<ExecutionReport, EngineClosed> {
+        self.request(|respond_to| Command::Cancel(id, respond_to))
+            .await
+    }
+
+    pub async fn amend(
+        &self,
+        id: u64,
+        amount: Qty,
+        price: PxTicks,
+    ) -> Result<ExecutionReport, EngineClosed> {
+        self.request(|respond_to| Command::Amend(id, amount, price, respond_to))
+            .await
+    }
+
+    pub fn subscribe_trades(&self) -> broadcast::Receiver<Trade> {
+        self.trades.subscribe()
+    }
+
+    async fn request<F>(&self, command: F) -> Result<ExecutionReport, EngineClosed>
+    where
+        F: FnOnce(oneshot::Sender<ExecutionReport>) -> Command,
+    {
+        let (respond_to, response) = oneshot::channel();
+        self.commands
+            .send(command(respond_to))
+            .await
+            .map_err(|_| EngineClosed)?;
+        response.await.map_err(|_| EngineClosed)
+    }
+}
+
+fn report(order_id: u64, status: OrderStatus) -> ExecutionReport {
+    ExecutionReport {
+        order_id,
+        status,
+        trades: Default::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::SubmitError;
+    use crate::orders::order_from_str;
+
+    #[tokio::test]
+    async fn test_submit_over_channels_reports_and_broadcasts_trades() {
+        let (engine, handle) = AsyncEngine::new(MatchingEngine::new(), 16);
+        let task = tokio::spawn(engine.run());
+        let mut trades = handle.subscribe_trades();
+
+        let resting = handle
+            .submit(order_from_str("4 10 10 1 0").unwrap())
+            .await
+            .unwrap();
+        let crossing = handle
+            .submit(order_from_str("8 4 10 2 0").unwrap())
+            .await
+            .unwrap();
+        let cancelled = handle.cancel(resting.order_id).await.unwrap();
+
+        assert_eq!((resting.order_id, crossing.order_id), (1, 2));
+        assert_eq!(cancelled.order_id, resting.order_id);
+        assert_eq!(resting.status, OrderStatus::Resting);
+        assert_eq!(crossing.status, OrderStatus::Filled);
+        assert_eq!(crossing.trades.len(), 1);
+        let trade = trades.recv().await.unwrap();
+        assert_eq!((trade.price, trade.amount), (10, Qty::units(4)));
+        assert_eq!(cancelled.status, OrderStatus::Cancelled);
+
+        drop(handle);
+        let engine = task.await.unwrap();
+        assert_eq!(engine.depth(10), (vec![], vec![]));
+    }
+
+    #[tokio::test]
+    async fn test_amend_over_channels_forwards_the_engine_report() {
+        let (engine, handle) = AsyncEngine::new(MatchingEngine::new(), 16);
+        tokio::spawn(engine.run());
+        handle
+            .submit(order_from_str("4 3 11 1 0").unwrap())
+            .await
+            .unwrap();
+        let bid = handle
+            .submit(order_from_str("8 5 10 2 0").unwrap())
+            .await
+            .unwrap();
+
+        let repriced = handle.amend(bid.order_id, Qty::units(5), 11).await.unwrap();
+        let unknown = handle.amend(7, Qty::units(5), 11).await.unwrap();
+
+        assert_eq!(repriced.order_id, bid.order_id);
+        assert_eq!(unknown.order_id, 7);
+        assert_eq!(repriced.status, OrderStatus::PartiallyFilled);
+        assert_eq!(repriced.trades.len(), 1);
+        assert_eq!(
+            unknown.status,
+            OrderStatus::Rejected(SubmitError::UnknownOrderId)
+        );
+    }
+}
@@ -0,0 +1,285 @@
+use std::collections::binary_heap::PeekMut;
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+
+use crate::engine::{decrement, PriceLevel};
+use crate::orders::{Order, PxTicks, Qty, Side};
+
+/// One side of an order book, as `MatchingEngine` stores it. Priority is
+/// `Order`'s `Ord`: the greatest order is the best.
+pub trait Book: Default {
+    fn insert(&mut self, order: Order);
+    fn peek_best(&self) -> Option<&Order>;
+    fn pop_best(&mut self) -> Option<Order>;
+    fn pop_worst(&mut self) -> Option<Order>;
+    fn remove_by_id(&mut self, id: u64) -> Option<Order>;
+    fn retain<F: FnMut(&Order) -> bool>(&mut self, keep: F);
+    /// Every order, in no particular order.
+    fn iter(&self) -> Box<dyn Iterator<Item = &Order> + '_>;
+    /// Every order, best first.
+    fn iter_priority(&self) -> Vec<&Order>;
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// How many orders the book has room for without reallocating.
+    fn capacity(&self) -> usize {
+        self.len()
+    }
+
+    fn shrink_to_fit(&mut self) {}
+
+    /// Takes a fill of `amount` off the best order, removing it once nothing
+    /// is left.
+    fn decrement_best(&mut self, amount: Qty) {
+        let mut best = self.pop_best().expect("decrement_best on an empty book");
+        best.amount = decrement(best.amount, amount);
+        best.filled += amount;
+        if best.amount > Qty::ZERO {
+            self.insert(best);
+        }
+    }
+
+    /// `(price, amount)` per price level, best first, at most `levels` of them.
+    fn aggregate(&self, levels: usize) -> Vec<PriceLevel> {
+        let mut aggregated: Vec<PriceLevel> = Vec::new();
+        for order in self.iter_priority() {
+            match aggregated.last_mut() {
+                Some((price, amount)) if *price == order.price => *amount += order.amount,
+                _ => {
+                    if aggregated.len() == levels {
+                        break;
+                    }
+                    aggregated.push((order.price, order.amount));
+                }
+            }
+        }
+        aggregated
+    }
+}
+
+/// `MatchingEngine`'s default backend without the `btreemap` feature. Cheap
+/// inserts and pops, but removal rebuilds the heap and priority walks sort.
+pub type HeapBook = BinaryHeap<Order>;
+
+impl Book for BinaryHeap<Order> {
+    fn insert(&mut self, order: Order) {
+        self.push(order);
+    }
+
+    fn peek_best(&self) -> Option<&Order> {
+        self.peek()
+    }
+
+    fn pop_best(&mut self) -> Option<Order> {
+        self.pop()
+    }
+
+    fn pop_worst(&mut self) -> Option<Order> {
+        let mut orders = std::mem::take(self).into_vec();
+        let worst = (0..orders.len()).min_by_key(|&i| orders[i])?;
+        let removed = orders.swap_remove(worst);
+        *self = BinaryHeap::from(orders);
+        Some(removed)
+    }
+
+    fn remove_by_id(&mut self, id: u64) -> Option<Order> {
+        if !BinaryHeap::iter(self).any(|order| order.id == id) {
+            return None;
+        }
+        let mut orders = std::mem::take(self).into_vec();
+        let index = orders.iter().position(|order| order.id == id)?;
+        let removed = orders.swap_remove(index);
+        *self = BinaryHeap::from(orders);
+        Some(removed)
+    }
+
+    fn retain<F: FnMut(&Order) -> bool>(&mut self, keep: F) {
+        BinaryHeap::retain(self, keep);
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = &Order> + '_> {
+        Box::new(BinaryHeap::iter(self))
+    }
+
+    fn iter_priority(&self) -> Vec<&Order> {
+        let mut orders: Vec<&Order> = BinaryHeap::iter(self).collect();
+        orders.sort_by(|a, b| b.cmp(a));
+        orders
+    }
+
+    fn len(&self) -> usize {
+        BinaryHeap::len(self)
+    }
+
+    fn capacity(&self) -> usize {
+        BinaryHeap::capacity(self)
+    }
+
+    fn shrink_to_fit(&mut self) {
+        BinaryHeap::shrink_to_fit(self);
+    }
+
+    fn decrement_best(&mut self, amount: Qty) {
+        let mut best = self.peek_mut().expect("decrement_best on an empty book");
+        if best.amount == amount {
+            PeekMut::pop(best);
+        } else {
+            best.amount = decrement(best.amount, amount);
+            best.filled += amount;
+        }
+    }
+}
+
+// price (negated for bids, so the best sorts first), timestamp, seq, id
+type BookKey = (PxTicks, i32, u64, u64);
+
+/// Orders kept sorted in a `BTreeMap` with an id index, so removal is
+/// logarithmic and aggregation walks the levels in order.
+#[derive(Clone, Debug, Default)]
+pub struct BTreeBook {
+    orders: BTreeMap<BookKey, Order>,
+    keys: HashMap<u64, BookKey>,
+}
+
+impl BTreeBook {
+    fn key(order: &Order) -> BookKey {
+        let price = match order.side {
+            Side::Bid => -order.price,
+            Side::Ask => order.price,
+        };
+        (price, order.timestamp, order.seq, order.id)
+    }
+}
+
+impl Book for BTreeBook {
+    fn insert(&mut self, order: Order) {
+        let key = Self::key(&order);
+        self.keys.insert(order.id, key);
+        self.orders.insert(key, order);
+    }
+
+    fn peek_best(&self) -> Option<&Order> {
+        self.orders.values().next()
+    }
+
+    fn pop_best(&mut self) -> Option<Order> {
+        let (_, order) = self.orders.pop_first()?;
+        self.keys.remove(&order.id);
+        Some(order)
+    }
+
+    fn pop_worst(&mut self) -> Option<Order> {
+        let (_, order) = self.orders.pop_last()?;
+        self.keys.remove(&order.id);
+        Some(order)
+    }
+
+    fn remove_by_id(&mut self, id: u64) -> Option<Order> {
+        let key = self.keys.remove(&id)?;
+        self.orders.remove(&key)
+    }
+
+    fn retain<F: FnMut(&Order) -> bool>(&mut self, mut keep: F) {
+        let keys = &mut self.keys;
+        self.orders.retain(|_, order| {
+            let kept = keep(order);
+            if !kept {
+                keys.remove(&order.id);
+            }
+            kept
+        });
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = &Order> + '_> {
+        Box::new(self.orders.values())
+    }
+
+    fn iter_priority(&self) -> Vec<&Order> {
+        self.orders.values().collect()
+    }
+
+    fn len(&self) -> usize {
+        self.orders.len()
+    }
+
+    fn decrement_best(&mut self, amount: Qty) {
+        let mut best = self
+            .orders
+            .first_entry()
+            .expect("decrement_best on an empty book");
+        if best.get().amount == amount {
+            self.keys.remove(&best.remove().id);
+        } else {
+            let best = best.get_mut();
+            best.amount = decrement(best.amount, amount);
+            best.filled += amount;
+        }
+    }
+}
+
+/// `MatchingEngine`'s default book: `BTreeBook` with the `btreemap` feature,
+/// `HeapBook` otherwise.
+#[cfg(not(feature = "btreemap"))]
+pub type DefaultBook = HeapBook;
+#[cfg(feature = "btreemap")]
+pub type DefaultBook = BTreeBook;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use crate::engine::MatchingEngine;
+    use crate::orders::Trade;
+
+    type Depth = (Vec<PriceLevel>, Vec<PriceLevel>);
+
+    fn run<B: Book>(orders: &[(Side, i64, PxTicks)]) -> (Vec<Trade>, Depth) {
+        let mut engine = MatchingEngine::<B>::with_books();
+        engine.set_clock(Box::new(MockClock::new(1_000)));
+        for (index, &(side, units, price)) in orders.iter().enumerate() {
+            engine.submit(Order::limit(side, Qty::units(units), price, index as i32));
+        }
+        (engine.trades().to_vec(), engine.depth(10))
+    }
+
+    #[test]
+    fn test_heap_and_btreemap_books_trade_identically() {
+        let orders = [
+            (Side::Ask, 5, 12),
+            (Side::Ask, 3, 11),
+            (Side::Bid, 4, 9),
+            (Side::Ask, 2, 11),
+            (Side::Bid, 6, 11),
+            (Side::Bid, 2, 10),
+            (Side::Ask, 9, 9),
+            (Side::Bid, 8, 12),
+        ];
+
+        let (heap_trades, heap_depth) = run::<HeapBook>(&orders);
+        let (btree_trades, btree_depth) = run::<BTreeBook>(&orders);
+
+        assert_eq!(heap_trades, btree_trades);
+        assert_eq!(heap_depth, btree_depth);
+        assert!(!heap_trades.is_empty());
+    }
+
+    #[test]
+    fn test_remove_by_id_on_both_books() {
+        let mut heap = HeapBook::default();
+        let mut btree = BTreeBook::default();
+        for id in 1..4 {
+            let mut order = Order::limit(Side::Bid, Qty::units(1), 10 + id as PxTicks, 0);
+            order.id = id;
+            heap.insert(order);
+            btree.insert(order);
+        }
+
+        assert_eq!(heap.remove_by_id(3).map(|order| order.id), Some(3));
+        assert_eq!(btree.remove_by_id(3).map(|order| order.id), Some(3));
+        assert_eq!(btree.remove_by_id(3), None);
+        assert_eq!(heap.peek_best().map(|order| order.id), Some(2));
+        assert_eq!(btree.peek_best().map(|order| order.id), Some(2));
+    }
+}
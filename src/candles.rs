@@ -0,0 +1,105 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::orders::{PxTicks, Qty, Trade};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Candle {
+    pub open: PxTicks,
+    pub high: PxTicks,
+    pub low: PxTicks,
+    pub close: PxTicks,
+    pub volume: Qty,
+    pub start_ts: u128,
+}
+
+/// Buckets trades by `interval` on their timestamp. Buckets without trades are
+/// skipped, open/close follow the order of `trades` and busted trades are ignored.
+/// A zero `interval` has no buckets, so it yields no candles.
+pub fn candles(trades: &[Trade], interval: Duration) -> Vec<Candle> {
+    candles_by(trades, interval.as_nanos())
+}
+
+/// `candles` for trade timestamps and an `interval` in any one unit.
+pub(crate) fn candles_by(trades: &[Trade], interval: u128) -> Vec<Candle> {
+    if interval == 0 {
+        return Vec::new();
+    }
+    let mut buckets = BTreeMap::<u128, Candle>::new();
+    for trade in trades.iter().filter(|trade| !trade.busted) {
+        let start_ts = trade.timestamp - trade.timestamp % interval;
+        let candle = buckets.entry(start_ts).or_insert(Candle {
+            open: trade.price,
+            high: trade.price,
+            low: trade.price,
+            close: trade.price,
+            volume: Qty::ZERO,
+            start_ts,
+        });
+        candle.high = candle.high.max(trade.price);
+        candle.low = candle.low.min(trade.price);
+        candle.close = trade.price;
+        candle.volume += trade.amount;
+    }
+    buckets.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(timestamp: u128, price: PxTicks, units: i64) -> Trade {
+        Trade {
+            sequence: 0,
+            executing_order_id: 1,
+            matched_order_id: 1,
+            timestamp,
+            amount: Qty::units(units),
+            price,
+            maker_edge_bps: None,
+            external: false,
+            buyer_account: None,
+            seller_account: None,
+            busted: false,
+        }
+    }
+
+    #[test]
+    fn test_candles_bucket_by_interval() {
+        let trades = [
+            trade(1_000_000_000, 10, 2),
+            trade(1_500_000_000, 12, 1),
+            trade(1_900_000_000, 9, 3),
+            trade(3_100_000_000, 11, 1),
+        ];
+
+        let candles = candles(&trades, Duration::from_secs(1));
+
+        assert_eq!(
+            candles,
+            vec![
+                Candle {
+                    open: 10,
+                    high: 12,
+                    low: 9,
+                    close: 9,
+                    volume: Qty::units(6),
+                    start_ts: 1_000_000_000,
+                },
+                Candle {
+                    open: 11,
+                    high: 11,
+                    low: 11,
+                    close: 11,
+                    volume: Qty::units(1),
+                    start_ts: 3_000_000_000,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_zero_interval_has_no_candles() {
+        assert_eq!(candles(&[trade(1_000, 10, 1)], Duration::ZERO), vec![]);
+    }
+}
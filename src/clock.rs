@@ -0,0 +1,108 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Source of engine time, in nanoseconds since the unix epoch.
+pub trait Clock: Send {
+    fn now(&self) -> u128;
+}
+
+impl Clock for Box<dyn Clock> {
+    fn now(&self) -> u128 {
+        (**self).now()
+    }
+}
+
+/// Resolution of engine timestamps: trade timestamps, `not_before`, `tick`
+/// and auction windows are all counted in this unit. Clocks always report
+/// nanoseconds; the engine truncates.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum TimeUnit {
+    Nanos,
+    Micros,
+    Millis,
+}
+
+impl TimeUnit {
+    fn nanos_per_unit(self) -> u128 {
+        match self {
+            TimeUnit::Nanos => 1,
+            TimeUnit::Micros => 1_000,
+            TimeUnit::Millis => 1_000_000,
+        }
+    }
+
+    pub fn from_nanos(self, nanos: u128) -> u128 {
+        nanos / self.nanos_per_unit()
+    }
+
+    /// `duration` counted in this unit.
+    pub fn of(self, duration: Duration) -> u128 {
+        self.from_nanos(duration.as_nanos())
+    }
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u128 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("WTF")
+            .as_nanos()
+    }
+}
+
+/// Manually driven clock for tests. Clones share the same time, so a test can
+/// keep one handle and give another to the engine.
+#[derive(Clone, Default)]
+pub struct MockClock {
+    nanos: Arc<AtomicU64>,
+}
+
+impl MockClock {
+    pub fn new(nanos: u64) -> Self {
+        Self {
+            nanos: Arc::new(AtomicU64::new(nanos)),
+        }
+    }
+
+    pub fn set(&self, nanos: u64) {
+        self.nanos.store(nanos, Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.nanos.fetch_add(by.as_nanos() as u64, Ordering::SeqCst);
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> u128 {
+        u128::from(self.nanos.load(Ordering::SeqCst))
+    }
+}
+
+/// Never goes backwards: a reading below the latest one is clamped to it, so a
+/// wall-clock step (e.g. NTP) can't reorder time priority or the tape.
+pub struct Monotonic<C> {
+    inner: C,
+    latest: Cell<u128>,
+}
+
+impl<C: Clock> Monotonic<C> {
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            latest: Cell::new(0),
+        }
+    }
+}
+
+impl<C: Clock> Clock for Monotonic<C> {
+    fn now(&self) -> u128 {
+        let now = self.inner.now().max(self.latest.get());
+        self.latest.set(now);
+        now
+    }
+}
@@ -0,0 +1,4417 @@
+use std::cmp::min;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::num::NonZeroU64;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::Duration;
+
+use crate::book::{Book, DefaultBook};
+use crate::candles::{self, Candle};
+use crate::clock::{Clock, Monotonic, SystemClock, TimeUnit};
+use crate::orders::{notional, Notional, Order, PxTicks, Qty, Side, TimeInForce, Trade};
+use crate::snapshot::BookSnapshot;
+use crate::venue::ExternalVenue;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum SubmitError {
+    BelowMinOrderQty,
+    AboveMaxOrderQty,
+    InvalidQuoteSides,
+    SelfCrossingQuote,
+    PriceLevelFull,
+    DuplicateOrderId,
+    UnknownOrderId,
+    WouldCross,
+    OutsidePriceBand,
+    OffTick,
+    TooSoon,
+    NoLiquidity,
+    BookFull,
+    /// Every order id up to `u64::MAX` has been handed out.
+    IdsExhausted,
+}
+
+impl SubmitError {
+    /// Stable machine-readable reason code.
+    pub fn code(&self) -> &'static str {
+        match self {
+            SubmitError::BelowMinOrderQty => "below_min_order_qty",
+            SubmitError::AboveMaxOrderQty => "above_max_order_qty",
+            SubmitError::InvalidQuoteSides => "invalid_quote_sides",
+            SubmitError::SelfCrossingQuote => "self_crossing_quote",
+            SubmitError::PriceLevelFull => "price_level_full",
+            SubmitError::DuplicateOrderId => "duplicate_order_id",
+            SubmitError::UnknownOrderId => "unknown_order_id",
+            SubmitError::WouldCross => "would_cross",
+            SubmitError::OutsidePriceBand => "outside_price_band",
+            SubmitError::OffTick => "off_tick",
+            SubmitError::TooSoon => "too_soon",
+            SubmitError::NoLiquidity => "no_liquidity",
+            SubmitError::BookFull => "book_full",
+            SubmitError::IdsExhausted => "ids_exhausted",
+        }
+    }
+}
+
+impl fmt::Display for SubmitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "order rejected: {}", self.code())
+    }
+}
+
+impl Error for SubmitError {}
+
+/// `(price, amount)`
+pub type PriceLevel = (PxTicks, Qty);
+
+/// See `MatchingEngine::set_on_expire`.
+pub type ExpireCallback = Box<dyn FnMut(&Order) + Send>;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum SeedError {
+    Crossed,
+    NonPositiveAmount,
+    IdsExhausted,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum BustError {
+    UnknownTrade,
+    AlreadyBusted,
+}
+
+/// `MatchingEngine::set_tick_size` was given a tick size that isn't positive.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct InvalidTickSize(pub PxTicks);
+
+/// The first order `MatchingEngine::from_orders` could not accept.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct BuildError {
+    pub index: usize,
+    pub reason: SubmitError,
+}
+
+/// The first broken invariant `MatchingEngine::validate_integrity` found.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum IntegrityError {
+    /// The best limit bid is at or above the best limit ask outside of collection.
+    CrossedBook {
+        bid: PxTicks,
+        ask: PxTicks,
+    },
+    NonPositiveAmount {
+        order_id: u64,
+    },
+    /// Two live orders share an id.
+    DuplicateOrderId {
+        order_id: u64,
+    },
+    /// A resting order's seq is shared or was never handed out.
+    InconsistentSeq {
+        order_id: u64,
+    },
+    /// Tape sequences don't strictly increase at this trade.
+    TapeOutOfOrder {
+        sequence: u64,
+    },
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum OrderStatus {
+    Resting,
+    PartiallyFilled,
+    Filled,
+    // waiting for the next batch auction
+    Queued,
+    // held back until its not_before time
+    Pending,
+    Cancelled,
+    Rejected(SubmitError),
+}
+
+/// How resting orders are ranked against an incoming one. Price always
+/// dominates: every better-priced level is exhausted before time priority picks
+/// between orders at the same price, however old the worse-priced ones are.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum MatchPriority {
+    PriceTime,
+}
+
+/// Which side of a match sets the trade price.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum TradePricePolicy {
+    Resting,
+    // falls back to the resting price for market orders
+    Incoming,
+    // the order with the earlier timestamp, the resting one on a tie
+    Earlier,
+}
+
+impl TradePricePolicy {
+    fn trade_price(&self, incoming: &Order, resting: &Order) -> PxTicks {
+        match self {
+            TradePricePolicy::Incoming if !incoming.is_market() => incoming.price,
+            TradePricePolicy::Earlier
+                if !incoming.is_market() && incoming.timestamp < resting.timestamp =>
+            {
+                incoming.price
+            }
+            _ => resting.price,
+        }
+    }
+}
+
+/// Where the time priority of an order comes from. `EngineAssigned` replaces
+/// the client timestamp with the order's arrival sequence number, so a client
+/// can't jump the queue by sending an early timestamp.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum TimestampPolicy {
+    ClientSupplied,
+    EngineAssigned,
+}
+
+/// What happens to the part of a market order the book couldn't fill.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum MarketRemainderPolicy {
+    Cancel,
+    // rest as a limit order at the price of its last fill, or cancel if none
+    RestAtLast,
+}
+
+/// Whether an amend that loses queue priority keeps the order id.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum AmendIdPolicy {
+    Preserve,
+    NewId,
+}
+
+/// One decision taken while matching an incoming order, see `submit_traced`.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum MatchStep {
+    Peeked {
+        order_id: u64,
+        crossed: bool,
+    },
+    Matched {
+        order_id: u64,
+        amount: Qty,
+        price: PxTicks,
+    },
+    Rested {
+        remainder: Qty,
+    },
+}
+
+/// Something the engine did that callers may want to hear about, see
+/// `drain_events`.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum EngineEvent {
+    /// A resting order was dropped to make room under `max_depth_per_side`, or a
+    /// queued one ranked too low to fit when its auction ran.
+    Evicted(Order),
+    /// A risk control (size limits, price band, level cap) refused an order or
+    /// an amend. `order_id` is 0 for orders the engine hadn't assigned an id yet.
+    Rejected { order_id: u64, reason: SubmitError },
+    /// A trade was recorded on the tape, with its sequence filled in.
+    Traded(Trade),
+    /// A trade was busted after the fact, see `bust_trade`.
+    TradeBusted(Trade),
+}
+
+/// Events waiting for `drain_events`, each also sent to every live subscriber.
+#[derive(Default)]
+struct EventLog {
+    pending: Vec<EngineEvent>,
+    subscribers: Vec<Sender<EngineEvent>>,
+}
+
+impl EventLog {
+    fn push(&mut self, event: EngineEvent) {
+        self.subscribers
+            .retain(|subscriber| subscriber.send(event).is_ok());
+        self.pending.push(event);
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ExecutionReport {
+    /// The order the report is about: its id once the engine accepted it, with
+    /// any id the engine assigned, otherwise whatever id it was submitted with.
+    pub order_id: u64,
+    pub status: OrderStatus,
+    pub trades: VecDeque<Trade>,
+}
+
+impl ExecutionReport {
+    fn rejected(order_id: u64, reason: SubmitError) -> Self {
+        Self {
+            order_id,
+            status: OrderStatus::Rejected(reason),
+            trades: VecDeque::new(),
+        }
+    }
+
+    pub fn error(&self) -> Option<SubmitError> {
+        match self.status {
+            OrderStatus::Rejected(reason) => Some(reason),
+            _ => None,
+        }
+    }
+
+    /// The report's trades summed per price, in the order the prices were hit.
+    pub fn consolidated_fills(&self) -> Vec<PriceLevel> {
+        let mut fills: Vec<PriceLevel> = Vec::new();
+        for trade in &self.trades {
+            match fills.iter_mut().find(|(price, _)| *price == trade.price) {
+                Some((_, amount)) => *amount += trade.amount,
+                None => fills.push((trade.price, trade.amount)),
+            }
+        }
+        fills
+    }
+}
+
+/// What a hypothetical market order would do to the book, see `market_impact`.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct MarketImpact {
+    pub avg_price: f64,
+    pub worst_price: PxTicks,
+    pub levels_consumed: usize,
+    /// How far `avg_price` is from the mid, positive when it's worse for the taker.
+    pub slippage_bps: f64,
+}
+
+/// Liquidity at one price on one side, see `level_stats`.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct LevelStats {
+    pub resting: Qty,
+    pub order_count: usize,
+    /// Session volume traded at this price by either side, busted trades excluded.
+    pub traded: Qty,
+}
+
+/// Everything `MatchingEngine` accumulates while running: books, queues, tape
+/// and the id/seq counters, so a loaded engine allocates exactly what the saved
+/// one would have. Configuration and undrained events are not included.
+#[derive(Clone, Debug)]
+pub struct EngineState {
+    asks: Vec<Order>,
+    bids: Vec<Order>,
+    auction_queue: Vec<Order>,
+    auction_started_at: u128,
+    collected_markets: Vec<Order>,
+    pending: Vec<Order>,
+    tape: Vec<Trade>,
+    submitted_count: u64,
+    rejected_count: u64,
+    trade_count: u64,
+    traded_notional: Notional,
+    next_order_id: Option<u64>,
+    next_seq: u64,
+    used_ids: HashSet<u64>,
+}
+
+/// Matches orders over two `Book`s, `DefaultBook` unless chosen otherwise.
+pub struct MatchingEngine<B: Book = DefaultBook> {
+    asks: B,
+    bids: B,
+    min_order_qty: Qty,
+    max_order_qty: Qty,
+    price_band: Option<(PxTicks, PxTicks)>,
+    tick_size: Option<PxTicks>,
+    max_orders_per_level: Option<usize>,
+    max_depth_per_side: Option<usize>,
+    submitted_count: u64,
+    rejected_count: u64,
+    trade_count: u64,
+    traded_notional: Notional,
+    /// `None` once every id has been handed out.
+    next_order_id: Option<u64>,
+    next_seq: u64,
+    clock: Box<dyn Clock>,
+    time_unit: TimeUnit,
+    auction_window: Option<Duration>,
+    min_rest: Option<Duration>,
+    collecting: bool,
+    // market orders submitted while collecting, in arrival order
+    collected_markets: Vec<Order>,
+    auction_queue: Vec<Order>,
+    auction_started_at: u128,
+    tape: Vec<Trade>,
+    max_tape_len: Option<usize>,
+    pending: Vec<Order>,
+    trade_price_policy: TradePricePolicy,
+    market_remainder_policy: MarketRemainderPolicy,
+    amend_id_policy: AmendIdPolicy,
+    timestamp_policy: TimestampPolicy,
+    reject_reused_ids: bool,
+    used_ids: HashSet<u64>,
+    events: EventLog,
+    record_maker_edge: bool,
+    external_venue: Option<Box<dyn ExternalVenue>>,
+    on_expire: Option<ExpireCallback>,
+}
+
+impl<B: Book> Default for MatchingEngine<B> {
+    fn default() -> Self {
+        Self::with_books()
+    }
+}
+
+impl MatchingEngine {
+    /// An engine on `DefaultBook`; see `with_books` for other backends.
+    pub fn new() -> Self {
+        Self::with_books()
+    }
+
+    /// A JSON array of `trades` in their `Serialize` form.
+    #[cfg(feature = "serde")]
+    pub fn trades_to_json(trades: &VecDeque<Trade>) -> String {
+        serde_json::to_string(trades).expect("trades always serialize")
+    }
+}
+
+impl<B: Book> MatchingEngine<B> {
+    /// An engine keeping each side in an empty `B`.
+    pub fn with_books() -> Self {
+        Self {
+            asks: B::default(),
+            bids: B::default(),
+            min_order_qty: Qty::from_base_units(1),
+            max_order_qty: Qty::MAX,
+            price_band: None,
+            tick_size: None,
+            max_orders_per_level: None,
+            max_depth_per_side: None,
+            submitted_count: 0,
+            rejected_count: 0,
+            trade_count: 0,
+            traded_notional: Notional::ZERO,
+            next_order_id: Some(1),
+            next_seq: 1,
+            clock: Box::new(Monotonic::new(SystemClock)),
+            time_unit: TimeUnit::Nanos,
+            auction_window: None,
+            min_rest: None,
+            collecting: false,
+            collected_markets: Vec::new(),
+            auction_queue: Vec::new(),
+            auction_started_at: 0,
+            tape: Vec::new(),
+            max_tape_len: None,
+            pending: Vec::new(),
+            trade_price_policy: TradePricePolicy::Resting,
+            market_remainder_policy: MarketRemainderPolicy::Cancel,
+            amend_id_policy: AmendIdPolicy::Preserve,
+            timestamp_policy: TimestampPolicy::ClientSupplied,
+            reject_reused_ids: false,
+            used_ids: HashSet::new(),
+            events: EventLog::default(),
+            record_maker_edge: false,
+            external_venue: None,
+            on_expire: None,
+        }
+    }
+
+    /// Builds an engine by submitting `orders` in sequence, matching as it goes.
+    /// Stops at the first rejected order and reports its index.
+    pub fn from_orders(orders: Vec<Order>) -> Result<Self, BuildError> {
+        let mut engine = Self::with_books();
+        for (index, order) in orders.into_iter().enumerate() {
+            if let Some(reason) = engine.submit(order).error() {
+                return Err(BuildError { index, reason });
+            }
+        }
+        Ok(engine)
+    }
+
+    /// Orders with an amount outside `min_order_qty..=max_order_qty` are rejected.
+    pub fn set_size_limits(&mut self, min_order_qty: Qty, max_order_qty: Qty) {
+        self.min_order_qty = min_order_qty;
+        self.max_order_qty = max_order_qty;
+    }
+
+    /// Limit orders priced outside `low..=high` are rejected.
+    pub fn set_price_band(&mut self, band: Option<(PxTicks, PxTicks)>) {
+        self.price_band = band;
+    }
+
+    /// Limit prices, on submit and on amend, must be a multiple of `tick_size`
+    /// in the engine's scaled price units; others are rejected as `OffTick`.
+    /// A tick size that isn't positive is refused and the old one kept.
+    pub fn set_tick_size(&mut self, tick_size: Option<PxTicks>) -> Result<(), InvalidTickSize> {
+        if let Some(tick_size) = tick_size.filter(|&tick_size| tick_size <= 0) {
+            return Err(InvalidTickSize(tick_size));
+        }
+        self.tick_size = tick_size;
+        Ok(())
+    }
+
+    /// Caps how many orders may rest at a single price on one side; limit orders
+    /// beyond the cap are rejected up front.
+    pub fn set_max_orders_per_level(&mut self, max_orders_per_level: Option<usize>) {
+        self.max_orders_per_level = max_orders_per_level;
+    }
+
+    /// Caps how many orders may rest on each side. When the side is full, the
+    /// worst-priority resting order is evicted to make room and reported as
+    /// `EngineEvent::Evicted`, unless the new order would rank below it: then
+    /// the new order is rejected with `BookFull`. Orders put back by `load` or a
+    /// failed amend never evict.
+    pub fn set_max_depth_per_side(&mut self, max_depth_per_side: Option<usize>) {
+        self.max_depth_per_side = max_depth_per_side;
+    }
+
+    /// Fills in `Trade::maker_edge_bps` for continuous matching. Off by default
+    /// as it needs the mid before every match.
+    pub fn set_record_maker_edge(&mut self, record_maker_edge: bool) {
+        self.record_maker_edge = record_maker_edge;
+    }
+
+    /// Whatever the book can't fill of an order is offered to `venue` once,
+    /// producing trades marked `external`.
+    pub fn set_external_venue(&mut self, venue: Option<Box<dyn ExternalVenue>>) {
+        self.external_venue = venue;
+    }
+
+    /// Called with every order that leaves the book because its trade TTL ran
+    /// out, right after the trades that expired it.
+    pub fn set_on_expire(&mut self, on_expire: Option<ExpireCallback>) {
+        self.on_expire = on_expire;
+    }
+
+    /// Keeps only the newest `max_tape_len` trades on the tape, dropping the
+    /// oldest. Everything computed from the tape (candles, PnL, busts, fill
+    /// estimates) then sees just that window; counters still cover the session.
+    pub fn set_max_tape_len(&mut self, max_tape_len: Option<usize>) {
+        self.max_tape_len = max_tape_len;
+    }
+
+    pub fn set_trade_price_policy(&mut self, policy: TradePricePolicy) {
+        self.trade_price_policy = policy;
+    }
+
+    /// A market order whose remainder is cancelled reports `Cancelled`, along
+    /// with whatever trades it did get.
+    pub fn set_market_remainder_policy(&mut self, policy: MarketRemainderPolicy) {
+        self.market_remainder_policy = policy;
+    }
+
+    /// Client-supplied ids of live orders are always unique. With this set, ids
+    /// seen since then can't be reused even after their order is gone.
+    pub fn set_reject_reused_ids(&mut self, reject_reused_ids: bool) {
+        self.reject_reused_ids = reject_reused_ids;
+    }
+
+    pub fn set_amend_id_policy(&mut self, policy: AmendIdPolicy) {
+        self.amend_id_policy = policy;
+    }
+
+    pub fn set_timestamp_policy(&mut self, policy: TimestampPolicy) {
+        self.timestamp_policy = policy;
+    }
+
+    /// The clock is wrapped in `Monotonic`, so engine time never goes backwards.
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.clock = Box::new(Monotonic::new(clock));
+    }
+
+    /// Nanoseconds by default, see `TimeUnit`.
+    pub fn set_time_unit(&mut self, time_unit: TimeUnit) {
+        self.time_unit = time_unit;
+    }
+
+    /// Switches from continuous matching to frequent batch auctions: submitted
+    /// orders are queued and only uncrossed, at a single price, by `tick` once
+    /// `window` has passed since the first order of the batch arrived.
+    pub fn set_auction_window(&mut self, window: Duration) {
+        self.auction_window = Some(window);
+    }
+
+    /// Resting orders can't be cancelled until they have been live for
+    /// `min_rest`; earlier cancels fail with `TooSoon`.
+    pub fn set_min_rest(&mut self, min_rest: Option<Duration>) {
+        self.min_rest = min_rest;
+    }
+
+    /// Pre-open collection: while set, submitted limit orders rest on the book as
+    /// they are, crossing or not, and nothing matches until `uncross` ends it.
+    /// Market orders are held aside and reported `Queued` until then.
+    pub fn set_collecting(&mut self, collecting: bool) {
+        self.collecting = collecting;
+    }
+
+    /// Empties both books, the auction queue and the trade tape. Configuration
+    /// (size limits, clock, auction window) is kept; ids and counters restart
+    /// from scratch only when `reset_counters` is set.
+    pub fn clear(&mut self, reset_counters: bool) {
+        self.bids = B::default();
+        self.asks = B::default();
+        self.auction_queue.clear();
+        self.collected_markets.clear();
+        self.pending.clear();
+        self.tape.clear();
+        self.events.pending.clear();
+        if reset_counters {
+            self.submitted_count = 0;
+            self.rejected_count = 0;
+            self.trade_count = 0;
+            self.traded_notional = Notional::ZERO;
+            self.next_order_id = Some(1);
+            self.next_seq = 1;
+            self.used_ids.clear();
+        }
+    }
+
+    pub fn save(&self) -> EngineState {
+        EngineState {
+            asks: self.asks.iter().copied().collect(),
+            bids: self.bids.iter().copied().collect(),
+            auction_queue: self.auction_queue.clone(),
+            auction_started_at: self.auction_started_at,
+            collected_markets: self.collected_markets.clone(),
+            pending: self.pending.clone(),
+            tape: self.tape.clone(),
+            submitted_count: self.submitted_count,
+            rejected_count: self.rejected_count,
+            trade_count: self.trade_count,
+            traded_notional: self.traded_notional,
+            next_order_id: self.next_order_id,
+            next_seq: self.next_seq,
+            used_ids: self.used_ids.clone(),
+        }
+    }
+
+    /// Replaces the running state with `state`, keeping configuration as `clear`
+    /// does. Replaying the same submissions afterwards yields the same ids, seqs
+    /// and trade sequences as the engine that saved it.
+    pub fn load(&mut self, state: EngineState) {
+        self.asks = B::default();
+        self.bids = B::default();
+        for order in state.asks.into_iter().chain(state.bids) {
+            self.restore(order);
+        }
+        self.auction_queue = state.auction_queue;
+        self.auction_started_at = state.auction_started_at;
+        self.collected_markets = state.collected_markets;
+        self.pending = state.pending;
+        self.tape = state.tape;
+        self.submitted_count = state.submitted_count;
+        self.rejected_count = state.rejected_count;
+        self.trade_count = state.trade_count;
+        self.traded_notional = state.traded_notional;
+        self.next_order_id = state.next_order_id;
+        self.next_seq = state.next_seq;
+        self.used_ids = state.used_ids;
+        self.events.pending.clear();
+    }
+
+    /// Takes the events raised since the last call, oldest first.
+    pub fn drain_events(&mut self) -> Vec<EngineEvent> {
+        std::mem::take(&mut self.events.pending)
+    }
+
+    /// A stream of every event from now on, in the order `drain_events` returns
+    /// them. Dropping the receiver unsubscribes.
+    pub fn subscribe(&mut self) -> Receiver<EngineEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.events.subscribers.push(sender);
+        receiver
+    }
+
+    pub fn match_priority(&self) -> MatchPriority {
+        MatchPriority::PriceTime
+    }
+
+    /// Every trade the engine has executed, oldest first.
+    pub fn trades(&self) -> &[Trade] {
+        &self.tape
+    }
+
+    /// Flags the trade with tape `sequence` as busted and takes its notional back
+    /// out of the counters. The trade stays on the tape; the filled quantity is
+    /// not reinstated on the book.
+    pub fn bust_trade(&mut self, sequence: u64) -> Result<Trade, BustError> {
+        let trade = self
+            .tape
+            .iter_mut()
+            .find(|trade| trade.sequence == sequence)
+            .ok_or(BustError::UnknownTrade)?;
+        if trade.busted {
+            return Err(BustError::AlreadyBusted);
+        }
+        trade.busted = true;
+        let trade = *trade;
+        self.traded_notional -= trade.notional();
+        self.events.push(EngineEvent::TradeBusted(trade));
+        Ok(trade)
+    }
+
+    /// OHLCV candles over the trade tape, see `candles::candles`. An interval
+    /// shorter than one tick of the engine's time unit is rounded up to one.
+    pub fn candles(&self, interval: Duration) -> Vec<Candle> {
+        let units = match self.time_unit.of(interval) {
+            0 if !interval.is_zero() => 1,
+            units => units,
+        };
+        candles::candles_by(&self.tape, units)
+    }
+
+    pub fn asks(&self) -> impl Iterator<Item = &Order> {
+        self.asks.iter()
+    }
+
+    pub fn bids(&self) -> impl Iterator<Item = &Order> {
+        self.bids.iter()
+    }
+
+    /// Number of orders resting on `side`.
+    pub fn order_count(&self, side: Side) -> usize {
+        self.book(side).len()
+    }
+
+    pub fn total_order_count(&self) -> usize {
+        self.bids.len() + self.asks.len()
+    }
+
+    /// Copies of the resting `(bids, asks)`, each best first.
+    pub fn book_snapshot(&self) -> (Vec<Order>, Vec<Order>) {
+        let copy = |book: &B| book.iter_priority().into_iter().copied().collect();
+        (copy(&self.bids), copy(&self.asks))
+    }
+
+    /// Aggregated `(price, amount)` levels for each side, best first.
+    pub fn depth(&self, levels: usize) -> (Vec<PriceLevel>, Vec<PriceLevel>) {
+        (self.bids.aggregate(levels), self.asks.aggregate(levels))
+    }
+
+    /// Like `depth`, with prices bucketed into groups of `group` ticks: bids
+    /// round down and asks up to the group boundary, so a bucket never looks
+    /// better than the orders in it.
+    pub fn depth_grouped(
+        &self,
+        levels: usize,
+        group: NonZeroU64,
+    ) -> (Vec<PriceLevel>, Vec<PriceLevel>) {
+        // no price is far enough from zero to tell larger groups apart
+        let group = PxTicks::try_from(group.get()).unwrap_or(PxTicks::MAX);
+        let (bids, asks) = self.depth(usize::MAX);
+        let bucket = |price: PxTicks, side: Side| match side {
+            Side::Bid => price.div_euclid(group) * group,
+            Side::Ask => -(-price).div_euclid(group) * group,
+        };
+        let grouped = |depth: Vec<PriceLevel>, side: Side| {
+            let mut grouped: Vec<PriceLevel> = Vec::new();
+            for (price, amount) in depth {
+                let price = bucket(price, side);
+                match grouped.last_mut() {
+                    Some(last) if last.0 == price => last.1 += amount,
+                    _ => grouped.push((price, amount)),
+                }
+            }
+            grouped.truncate(levels);
+            grouped
+        };
+        (grouped(bids, Side::Bid), grouped(asks, Side::Ask))
+    }
+
+    /// Full-depth snapshot of both sides, see `BookSnapshot::diff`.
+    pub fn snapshot(&self) -> BookSnapshot {
+        let (bids, asks) = self.depth(usize::MAX);
+        BookSnapshot { bids, asks }
+    }
+
+    /// Whether `order` would trade on arrival against the opposite top of book,
+    /// i.e. pay taker rather than maker fees.
+    pub fn would_be_taker(&self, order: &Order) -> bool {
+        let other_side = match order.side {
+            Side::Bid => Side::Ask,
+            Side::Ask => Side::Bid,
+        };
+        self.book(other_side)
+            .peek_best()
+            .is_some_and(|best| order.matches(best))
+    }
+
+    /// Total notional and worst price of sweeping the opposite book for `qty` on
+    /// behalf of `side`, or `None` if the book can't fill it.
+    pub fn cost_to_fill(&self, side: Side, qty: Qty) -> Option<(Notional, PxTicks)> {
+        let fills = self.sweep(side, qty);
+        if fills.iter().map(|&(_, amount)| amount).sum::<Qty>() < qty {
+            return None;
+        }
+        let total = fills
+            .iter()
+            .map(|&(price, amount)| notional(price, amount))
+            .sum();
+        Some((total, fills.last()?.0))
+    }
+
+    /// `None` if nothing rests at `price` on `side` and nothing has traded there.
+    pub fn level_stats(&self, side: Side, price: PxTicks) -> Option<LevelStats> {
+        let book = match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        };
+        let resting: Vec<&Order> = book.iter().filter(|order| order.price == price).collect();
+        let traded = self
+            .tape
+            .iter()
+            .filter(|trade| !trade.busted && trade.price == price)
+            .map(|trade| trade.amount)
+            .sum();
+        if resting.is_empty() && traded == Qty::ZERO {
+            return None;
+        }
+        Some(LevelStats {
+            resting: resting.iter().map(|order| order.amount).sum(),
+            order_count: resting.len(),
+            traded,
+        })
+    }
+
+    /// Trades `id` took part in, as the incoming or the resting order, oldest
+    /// first. Limited to what is still on the tape.
+    pub fn order_trades(&self, id: u64) -> Vec<Trade> {
+        self.tape
+            .iter()
+            .filter(|trade| trade.executing_order_id == id || trade.matched_order_id == id)
+            .copied()
+            .collect()
+    }
+
+    /// Average and worst price of a market order of `qty` on `side`, without
+    /// touching the book. `None` if the book can't fill it or has no mid.
+    pub fn market_impact(&self, side: Side, qty: Qty) -> Option<MarketImpact> {
+        let (total, worst_price) = self.cost_to_fill(side, qty)?;
+        let mid = self.mid()?;
+        let avg_price = total.base_units() as f64 / qty.base_units() as f64;
+        let mut prices: Vec<PxTicks> = self
+            .sweep(side, qty)
+            .iter()
+            .map(|&(price, _)| price)
+            .collect();
+        prices.dedup();
+        let slippage = match side {
+            Side::Bid => avg_price - mid,
+            Side::Ask => mid - avg_price,
+        };
+        Some(MarketImpact {
+            avg_price,
+            worst_price,
+            levels_consumed: prices.len(),
+            slippage_bps: slippage / mid * 10_000.0,
+        })
+    }
+
+    /// Realized PnL of `account` over the trade tape, matching its buys and sells
+    /// first-in first-out, ignoring busted trades. Open positions don't count.
+    pub fn realized_pnl(&self, account: u64) -> Notional {
+        let mut lots = VecDeque::new();
+        let mut pnl = Notional::ZERO;
+        for trade in self.tape.iter().filter(|trade| !trade.busted) {
+            if trade.buyer_account == Some(account) {
+                pnl += fifo_fill(&mut lots, Side::Bid, trade.price, trade.amount);
+            }
+            if trade.seller_account == Some(account) {
+                pnl += fifo_fill(&mut lots, Side::Ask, trade.price, trade.amount);
+            }
+        }
+        pnl
+    }
+
+    /// Rank of a resting order within its price level and the quantity queued
+    /// ahead of it.
+    pub fn queue_position(&self, id: u64) -> Option<(usize, Qty)> {
+        let order = self.find(id)?;
+        let ahead: Vec<&Order> = self
+            .book(order.side)
+            .iter_priority()
+            .into_iter()
+            .take_while(|other| other.id != id)
+            .filter(|other| other.price == order.price)
+            .collect();
+        Some((ahead.len(), ahead.iter().map(|other| other.amount).sum()))
+    }
+
+    /// Rough chance that a resting order fills: the volume traded over the last
+    /// `lookback` measured against the quantity that must trade before the order
+    /// is done (the queue ahead plus its own amount), capped at 1.
+    pub fn fill_estimate(&self, id: u64, lookback: Duration) -> Option<f64> {
+        let (_, ahead) = self.queue_position(id)?;
+        let needed = ahead + self.find(id)?.amount;
+        let since = self.now().saturating_sub(self.time_unit.of(lookback));
+        let recent: Qty = self
+            .tape
+            .iter()
+            .filter(|trade| trade.timestamp >= since)
+            .map(|trade| trade.amount)
+            .sum();
+        let ratio = recent.base_units() as f64 / needed.base_units() as f64;
+        Some(ratio.min(1.0))
+    }
+
+    /// Self-test of the engine's invariants, cheap enough to run now and then in
+    /// production. Reports the first violation found.
+    pub fn validate_integrity(&self) -> Result<(), IntegrityError> {
+        if let (Some(bid), Some(ask)) = (self.bids.peek_best(), self.asks.peek_best()) {
+            if !self.collecting && !bid.is_market() && !ask.is_market() && bid.price >= ask.price {
+                return Err(IntegrityError::CrossedBook {
+                    bid: bid.price,
+                    ask: ask.price,
+                });
+            }
+        }
+        let mut ids = HashSet::new();
+        let mut seqs = HashSet::new();
+        for order in self.bids.iter().chain(self.asks.iter()) {
+            let order_id = order.id;
+            if order.amount <= Qty::ZERO {
+                return Err(IntegrityError::NonPositiveAmount { order_id });
+            }
+            if !ids.insert(order_id) {
+                return Err(IntegrityError::DuplicateOrderId { order_id });
+            }
+            if order.seq >= self.next_seq || !seqs.insert(order.seq) {
+                return Err(IntegrityError::InconsistentSeq { order_id });
+            }
+        }
+        for pair in self.tape.windows(2) {
+            if pair[1].sequence <= pair[0].sequence {
+                return Err(IntegrityError::TapeOutOfOrder {
+                    sequence: pair[1].sequence,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Warm-starts the book from `(price, amount)` levels, e.g. another venue's L2.
+    /// Every level becomes one resting order with an engine-assigned id and a
+    /// synthetic timestamp of 0, so it keeps priority over anything submitted later.
+    /// Size limits are not applied to seeded levels.
+    pub fn seed_from_depth(
+        &mut self,
+        bids: &[PriceLevel],
+        asks: &[PriceLevel],
+    ) -> Result<(), SeedError> {
+        if bids
+            .iter()
+            .chain(asks.iter())
+            .any(|&(_, amount)| amount <= Qty::ZERO)
+        {
+            return Err(SeedError::NonPositiveAmount);
+        }
+        let levels = (bids.len() + asks.len()) as u64;
+        if self
+            .next_order_id
+            .map_or(levels > 0, |next| u64::MAX - next + 1 < levels)
+        {
+            return Err(SeedError::IdsExhausted);
+        }
+        let best_bid = bids
+            .iter()
+            .map(|&(price, _)| price)
+            .chain(self.bids.peek_best().map(|order| order.price))
+            .max();
+        let best_ask = asks
+            .iter()
+            .map(|&(price, _)| price)
+            .chain(self.asks.peek_best().map(|order| order.price))
+            .min();
+        if let (Some(best_bid), Some(best_ask)) = (best_bid, best_ask) {
+            if best_bid >= best_ask {
+                return Err(SeedError::Crossed);
+            }
+        }
+
+        for &(price, amount) in bids {
+            let mut order = Order::limit(Side::Bid, amount, price, 0);
+            order.id = self.next_id().expect("checked above");
+            order.seq = self.next_seq();
+            self.bids.insert(order);
+        }
+        for &(price, amount) in asks {
+            let mut order = Order::limit(Side::Ask, amount, price, 0);
+            order.id = self.next_id().expect("checked above");
+            order.seq = self.next_seq();
+            self.asks.insert(order);
+        }
+        Ok(())
+    }
+
+    /// Removes a live order, whether resting, pending or queued for an auction.
+    /// The returned order's `amount` is the cancelled remainder and `filled` what
+    /// had already traded.
+    pub fn cancel(&mut self, id: u64) -> Result<Order, SubmitError> {
+        if let (Some(min_rest), Some(order)) = (self.min_rest, self.find(id)) {
+            if self.now() < order.live_since + self.time_unit.of(min_rest) {
+                return Err(SubmitError::TooSoon);
+            }
+        }
+        if let Some(order) = self
+            .bids
+            .remove_by_id(id)
+            .or_else(|| self.asks.remove_by_id(id))
+        {
+            return Ok(order);
+        }
+        for queue in [
+            &mut self.pending,
+            &mut self.auction_queue,
+            &mut self.collected_markets,
+        ]
+        .iter_mut()
+        {
+            if let Some(index) = queue.iter().position(|order| order.id == id) {
+                return Ok(queue.remove(index));
+            }
+        }
+        Err(SubmitError::UnknownOrderId)
+    }
+
+    /// Releases memory held by the books after many cancels. Cancels remove orders
+    /// eagerly, so there are no dead entries to drop; this only shrinks capacity.
+    /// It reallocates, so it never runs on its own: call it off the hot path.
+    pub fn compact(&mut self) {
+        self.bids.shrink_to_fit();
+        self.asks.shrink_to_fit();
+        self.pending.shrink_to_fit();
+        self.auction_queue.shrink_to_fit();
+        self.collected_markets.shrink_to_fit();
+    }
+
+    /// Orders accepted but not active yet (`not_before` in the future or an
+    /// untouched market-if-touched trigger), in arrival order. They don't show
+    /// up in `depth`.
+    pub fn pending_orders(&self) -> Vec<&Order> {
+        self.pending.iter().collect()
+    }
+
+    /// Like `cancel`, but only for orders that haven't activated yet.
+    pub fn cancel_pending(&mut self, id: u64) -> Option<Order> {
+        let index = self.pending.iter().position(|order| order.id == id)?;
+        Some(self.pending.remove(index))
+    }
+
+    /// Cancels the order at queue position `index` within the `price` level of
+    /// `side`. The rest of the level keeps its relative priority.
+    pub fn cancel_at(
+        &mut self,
+        side: Side,
+        price: PxTicks,
+        index: usize,
+    ) -> Result<Order, SubmitError> {
+        let id = self
+            .book(side)
+            .iter_priority()
+            .into_iter()
+            .filter(|order| order.price == price)
+            .nth(index)
+            .ok_or(SubmitError::UnknownOrderId)?
+            .id;
+        self.cancel(id)
+    }
+
+    /// Changes the amount and price of a resting order. Reducing the amount keeps
+    /// its queue priority; a new price or a larger amount sends it to the back of
+    /// its level and may match it like a new order; under `AmendIdPolicy::NewId`
+    /// it then takes the next engine-assigned id. A failed amendment leaves the
+    /// order as it was.
+    pub fn amend(
+        &mut self,
+        id: u64,
+        new_amount: Qty,
+        new_price: PxTicks,
+    ) -> Result<ExecutionReport, SubmitError> {
+        let original = self
+            .bids
+            .remove_by_id(id)
+            .or_else(|| self.asks.remove_by_id(id))
+            .ok_or(SubmitError::UnknownOrderId)?;
+        let mut amended = original;
+        amended.amount = new_amount;
+        amended.price = new_price;
+        // like on submit, the risk checks see the price it will actually rest at
+        if let Err(reason) = self
+            .book_or_cancel(&mut amended)
+            .and_then(|_| self.check_risk(&amended))
+        {
+            self.restore(original);
+            return Err(reason);
+        }
+        if amended.price == original.price && new_amount <= original.amount {
+            self.restore(amended);
+            return Ok(ExecutionReport {
+                order_id: amended.id,
+                status: OrderStatus::Resting,
+                trades: VecDeque::new(),
+            });
+        }
+
+        if self.amend_id_policy == AmendIdPolicy::NewId {
+            amended.id = match self.next_id() {
+                Ok(id) => id,
+                Err(reason) => {
+                    self.restore(original);
+                    return Err(reason);
+                }
+            };
+            if self.reject_reused_ids {
+                self.used_ids.insert(amended.id);
+            }
+        }
+        // behind everything already resting at the new price
+        amended.timestamp = self
+            .book(amended.side)
+            .iter()
+            .filter(|other| other.price == amended.price)
+            .map(|other| other.timestamp)
+            .fold(amended.timestamp, i32::max);
+        amended.seq = self.next_seq();
+        // like a new order, so it waits for the auction or uncross too
+        let report = self.route(amended, None);
+        if let Some(reason) = report.error() {
+            self.restore(original);
+            return Err(reason);
+        }
+        Ok(report)
+    }
+
+    pub fn submit(&mut self, order: Order) -> ExecutionReport {
+        self.submit_order(order, None)
+    }
+
+    /// Like `submit`, but also returns every step the matching loop took.
+    pub fn submit_traced(&mut self, order: Order) -> (ExecutionReport, Vec<MatchStep>) {
+        let mut steps = Vec::new();
+        let report = self.submit_order(order, Some(&mut steps));
+        (report, steps)
+    }
+
+    fn submit_order(
+        &mut self,
+        mut order: Order,
+        trace: Option<&mut Vec<MatchStep>>,
+    ) -> ExecutionReport {
+        self.submitted_count += 1;
+        // risk checks see the price a book-or-cancel order will actually rest at
+        if let Err(reason) = self
+            .book_or_cancel(&mut order)
+            .and_then(|_| self.check_risk(&order))
+            .and_then(|_| self.check_id(&order))
+        {
+            self.rejected_count += 1;
+            return ExecutionReport::rejected(order.id, reason);
+        }
+        if order.id == 0 {
+            match self.next_id() {
+                Ok(id) => order.id = id,
+                Err(reason) => {
+                    self.rejected_count += 1;
+                    return ExecutionReport::rejected(order.id, reason);
+                }
+            }
+        } else if self.next_order_id.is_some_and(|next| order.id >= next) {
+            // nothing above u64::MAX is left to assign
+            self.next_order_id = order.id.checked_add(1);
+        }
+        if self.reject_reused_ids {
+            self.used_ids.insert(order.id);
+        }
+        order.seq = self.next_seq();
+        if !order.is_triggered() {
+            self.pending.push(order);
+            return ExecutionReport {
+                order_id: order.id,
+                status: OrderStatus::Pending,
+                trades: VecDeque::new(),
+            };
+        }
+        if self.timestamp_policy == TimestampPolicy::EngineAssigned {
+            order.timestamp = i32::try_from(order.seq).unwrap_or(i32::MAX);
+        }
+        if order
+            .not_before
+            .is_some_and(|not_before| not_before > self.now())
+        {
+            self.pending.push(order);
+            return ExecutionReport {
+                order_id: order.id,
+                status: OrderStatus::Pending,
+                trades: VecDeque::new(),
+            };
+        }
+        self.route(order, trace)
+    }
+
+    fn route(&mut self, mut order: Order, trace: Option<&mut Vec<MatchStep>>) -> ExecutionReport {
+        order.live_since = self.now();
+        if self.collecting && order.is_market() {
+            self.collected_markets.push(order);
+            return ExecutionReport {
+                order_id: order.id,
+                status: OrderStatus::Queued,
+                trades: VecDeque::new(),
+            };
+        }
+        if self.collecting {
+            if let Err(reason) = self.rest(order) {
+                self.rejected_count += 1;
+                return ExecutionReport::rejected(order.id, reason);
+            }
+            return ExecutionReport {
+                order_id: order.id,
+                status: OrderStatus::Resting,
+                trades: VecDeque::new(),
+            };
+        }
+        if self.auction_window.is_some() {
+            if self.auction_queue.is_empty() {
+                self.auction_started_at = self.now();
+            }
+            self.auction_queue.push(order);
+            return ExecutionReport {
+                order_id: order.id,
+                status: OrderStatus::Queued,
+                trades: VecDeque::new(),
+            };
+        }
+        if let Err(reason) = self.check_liquidity(&order) {
+            self.rejected_count += 1;
+            return ExecutionReport::rejected(order.id, reason);
+        }
+        let mut report = self.execute_order(order, trace);
+        if report.error().is_some() {
+            self.rejected_count += 1;
+        }
+        self.record_trades(&mut report.trades);
+        report
+    }
+
+    /// Activates pending orders whose `not_before` has been reached and runs
+    /// the batch auction once its window has elapsed at `now`.
+    pub fn tick(&mut self, now: u128) -> VecDeque<Trade> {
+        let mut trades = self.activate_pending(now);
+        trades.extend(self.run_auction(now));
+        trades
+    }
+
+    fn activate_pending(&mut self, now: u128) -> VecDeque<Trade> {
+        let (mut ready, waiting): (Vec<Order>, Vec<Order>) =
+            self.pending.drain(..).partition(|order| {
+                order.is_triggered() && order.not_before.is_none_or(|not_before| not_before <= now)
+            });
+        self.pending = waiting;
+        ready.sort_by_key(|order| order.not_before);
+        let mut trades = VecDeque::new();
+        for order in ready {
+            trades.extend(self.route(order, None).trades);
+        }
+        trades
+    }
+
+    fn run_auction(&mut self, now: u128) -> VecDeque<Trade> {
+        let window = match self.auction_window {
+            Some(window) => window,
+            None => return VecDeque::new(),
+        };
+        if self.auction_queue.is_empty()
+            || now < self.auction_started_at + self.time_unit.of(window)
+        {
+            return VecDeque::new();
+        }
+        let (markets, limits): (Vec<Order>, Vec<Order>) = std::mem::take(&mut self.auction_queue)
+            .into_iter()
+            .partition(Order::is_market);
+        for order in limits {
+            if self.rest(order).is_err() {
+                self.events.push(EngineEvent::Evicted(order));
+            }
+        }
+        self.uncross_with(markets)
+    }
+
+    /// Matches a crossed book in one go at the single price that executes the
+    /// most volume. Ties go to the smallest imbalance, then to the lowest price.
+    /// Market orders held back while collecting trade first on their side, and
+    /// whatever they can't fill is cancelled.
+    pub fn uncross(&mut self) -> VecDeque<Trade> {
+        let markets = std::mem::take(&mut self.collected_markets);
+        self.uncross_with(markets)
+    }
+
+    /// `uncross`, with `markets` trading ahead of the limit orders on their side
+    /// at the clearing price. Whatever they can't fill is cancelled, never rested.
+    fn uncross_with(&mut self, markets: Vec<Order>) -> VecDeque<Trade> {
+        self.collecting = false;
+        let (mut market_bids, mut market_asks): (VecDeque<Order>, VecDeque<Order>) = markets
+            .into_iter()
+            .partition(|order| order.side == Side::Bid);
+        let mut trades = VecDeque::<Trade>::new();
+        let price = match self.clearing_price(&market_bids, &market_asks) {
+            Some(price) => price,
+            None => return trades,
+        };
+        let timestamp = self.now();
+        loop {
+            let bid = market_bids.front().or_else(|| self.bids.peek_best());
+            let ask = market_asks.front().or_else(|| self.asks.peek_best());
+            let (bid, ask) = match (bid, ask) {
+                (Some(bid), Some(ask)) if bid.accepts_price(price) && ask.accepts_price(price) => {
+                    (*bid, *ask)
+                }
+                _ => break,
+            };
+            let amount = min(bid.amount, ask.amount);
+            let (buyer_account, seller_account) = (bid.account, ask.account);
+            let (bid_id, ask_id) = (bid.id, ask.id);
+            take_from(&mut market_bids, &mut self.bids, amount);
+            take_from(&mut market_asks, &mut self.asks, amount);
+            trades.push_back(Trade {
+                sequence: 0,
+                executing_order_id: bid_id,
+                matched_order_id: ask_id,
+                timestamp,
+                amount,
+                price,
+                maker_edge_bps: None,
+                external: false,
+                buyer_account,
+                seller_account,
+                busted: false,
+            });
+        }
+        self.record_trades(&mut trades);
+        trades
+    }
+
+    /// Submits a two-sided quote. Both orders go through every check up front,
+    /// so either both are submitted or both are rejected with the same reason.
+    pub fn quote(&mut self, mut bid: Order, mut ask: Order) -> (ExecutionReport, ExecutionReport) {
+        if let Err(reason) = self.validate_quote(&mut bid, &mut ask) {
+            self.submitted_count += 2;
+            self.rejected_count += 2;
+            return (
+                ExecutionReport::rejected(bid.id, reason),
+                ExecutionReport::rejected(ask.id, reason),
+            );
+        }
+        (self.submit(bid), self.submit(ask))
+    }
+
+    /// Places post-only limit orders at each `(price, amount)` on `side`, e.g. a
+    /// market maker's quote grid. A level that would trade against the opposite
+    /// book is rejected with `WouldCross` rather than matched. Like seeded
+    /// levels, grid orders carry timestamp 0.
+    pub fn place_grid(&mut self, side: Side, levels: &[PriceLevel]) -> Vec<ExecutionReport> {
+        levels
+            .iter()
+            .map(|&(price, amount)| self.submit_post_only(Order::limit(side, amount, price, 0)))
+            .collect()
+    }
+
+    /// Replaces every live order of `account` with `quotes`, which are tagged
+    /// with the account and placed post-only like `place_grid`. Reports follow
+    /// the order of `quotes`. The quotes are checked before anything is
+    /// cancelled: if one fails, all are rejected with its reason and the old
+    /// orders stay live.
+    pub fn mass_quote(&mut self, account: u64, mut quotes: Vec<Order>) -> Vec<ExecutionReport> {
+        for quote in quotes.iter_mut() {
+            quote.account = Some(account);
+        }
+        let stale: Vec<u64> = self
+            .bids
+            .iter()
+            .chain(self.asks.iter())
+            .chain(self.pending.iter())
+            .chain(self.auction_queue.iter())
+            .chain(self.collected_markets.iter())
+            .filter(|order| order.account == Some(account))
+            .map(|order| order.id)
+            .collect();
+        if let Err(reason) = self.validate_mass_quote(account, &stale, &quotes) {
+            self.submitted_count += quotes.len() as u64;
+            self.rejected_count += quotes.len() as u64;
+            return quotes
+                .iter()
+                .map(|quote| ExecutionReport::rejected(quote.id, reason))
+                .collect();
+        }
+        for id in stale {
+            self.cancel(id).expect("stale quotes are cancellable");
+        }
+        quotes.into_iter().map(|quote| self.submit(quote)).collect()
+    }
+
+    /// Canonical JSON of both books in priority order plus the engine counters.
+    /// The output only depends on the book contents, never on insertion order.
+    /// Amounts and notional are exact decimal strings, the way serde writes them.
+    pub fn snapshot_json(&self) -> String {
+        format!(
+            "{{\"bids\":{},\"asks\":{},\"counters\":{{\"submitted\":{},\"rejected\":{},\"trades\":{},\"notional\":\"{}\"}}}}",
+            orders_json(&self.bids),
+            orders_json(&self.asks),
+            self.submitted_count,
+            self.rejected_count,
+            self.trade_count,
+            self.traded_notional
+        )
+    }
+
+    /// Read-only walk of the book opposite to `side`: the `(price, amount)` each
+    /// resting order would fill for an order of `qty`, best first.
+    fn sweep(&self, side: Side, qty: Qty) -> Vec<PriceLevel> {
+        let other_side = match side {
+            Side::Bid => &self.asks,
+            Side::Ask => &self.bids,
+        };
+        let mut remaining = qty;
+        let mut fills = Vec::new();
+        for order in other_side.iter_priority() {
+            if remaining == Qty::ZERO {
+                break;
+            }
+            let amount = min(remaining, order.amount);
+            fills.push((order.price, amount));
+            remaining -= amount;
+        }
+        fills
+    }
+
+    fn submit_post_only(&mut self, order: Order) -> ExecutionReport {
+        if self.would_be_taker(&order) {
+            self.submitted_count += 1;
+            self.rejected_count += 1;
+            return ExecutionReport::rejected(order.id, SubmitError::WouldCross);
+        }
+        self.submit(order)
+    }
+
+    /// How much of `order` the opposite book could fill right now, within its
+    /// limit price, plus what the external venue would take of the rest, the
+    /// way `execute_order` sends it there.
+    fn fillable(&self, order: &Order) -> Qty {
+        let other_side = match order.side {
+            Side::Bid => Side::Ask,
+            Side::Ask => Side::Bid,
+        };
+        let internal: Qty = self
+            .book(other_side)
+            .iter_priority()
+            .into_iter()
+            .take_while(|resting| order.matches(resting))
+            .map(|resting| resting.amount)
+            .sum();
+        let left = order.amount - min(internal, order.amount);
+        let external = self
+            .external_venue
+            .as_deref()
+            .filter(|_| left > Qty::ZERO)
+            .and_then(|venue| venue.quote(order.side, left))
+            .filter(|&(price, _)| order.accepts_price(price))
+            .map_or(Qty::ZERO, |(_, available)| min(available, left));
+        internal + external
+    }
+
+    /// Slides a `BookOrCancel` order that would take liquidity back to one tick
+    /// short of the opposite top, if that is within its tolerance.
+    fn book_or_cancel(&self, order: &mut Order) -> Result<(), SubmitError> {
+        let tolerance = match order.time_in_force {
+            TimeInForce::BookOrCancel { tolerance } => tolerance,
+            _ => return Ok(()),
+        };
+        if !self.would_be_taker(order) {
+            return Ok(());
+        }
+        let other_side = match order.side {
+            Side::Bid => Side::Ask,
+            Side::Ask => Side::Bid,
+        };
+        let best = self.book(other_side).peek_best().unwrap().price;
+        let tick_size = self.tick_size.unwrap_or(1);
+        let (crossed_by, repriced) = match order.side {
+            Side::Bid => (order.price - best, best - tick_size),
+            Side::Ask => (best - order.price, best + tick_size),
+        };
+        // a partial tick counts as a whole one
+        let crossed_ticks = (crossed_by + tick_size - 1) / tick_size;
+        if order.is_market() || crossed_ticks > tolerance {
+            return Err(SubmitError::WouldCross);
+        }
+        order.price = repriced;
+        Ok(())
+    }
+
+    fn now(&self) -> u128 {
+        self.time_unit.from_nanos(self.clock.now())
+    }
+
+    fn mid(&self) -> Option<f64> {
+        let bid = self.bids.peek_best()?.price;
+        let ask = self.asks.peek_best()?.price;
+        Some((bid as f64 + ask as f64) / 2.0)
+    }
+
+    fn book(&self, side: Side) -> &B {
+        match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        }
+    }
+
+    fn rest(&mut self, mut order: Order) -> Result<(), SubmitError> {
+        order.expires_at_trade = order
+            .cancel_after_trades
+            .map(|trades_left| self.trade_count + trades_left);
+        let book = match order.side {
+            Side::Bid => &mut self.bids,
+            Side::Ask => &mut self.asks,
+        };
+        push_bounded(book, order, self.max_depth_per_side, &mut self.events)
+    }
+
+    /// Puts back an order that was already resting, without evicting anything.
+    fn restore(&mut self, order: Order) {
+        match order.side {
+            Side::Bid => self.bids.insert(order),
+            Side::Ask => self.asks.insert(order),
+        }
+    }
+
+    fn find(&self, id: u64) -> Option<&Order> {
+        self.bids
+            .iter()
+            .chain(self.asks.iter())
+            .find(|order| order.id == id)
+    }
+
+    /// See `uncross`. Market orders add to demand or supply at every price but
+    /// don't propose one.
+    fn clearing_price(
+        &self,
+        market_bids: &VecDeque<Order>,
+        market_asks: &VecDeque<Order>,
+    ) -> Option<PxTicks> {
+        let mut prices: Vec<PxTicks> = self
+            .bids
+            .iter()
+            .chain(self.asks.iter())
+            .filter(|o| !o.is_market())
+            .map(|o| o.price)
+            .collect();
+        prices.sort_unstable();
+        prices.dedup();
+
+        // (price, volume, imbalance)
+        let mut best: Option<(PxTicks, Qty, Qty)> = None;
+        for price in prices {
+            let demand: Qty = self
+                .bids
+                .iter()
+                .chain(market_bids.iter())
+                .filter(|o| o.accepts_price(price))
+                .map(|o| o.amount)
+                .sum();
+            let supply: Qty = self
+                .asks
+                .iter()
+                .chain(market_asks.iter())
+                .filter(|o| o.accepts_price(price))
+                .map(|o| o.amount)
+                .sum();
+            let volume = min(demand, supply);
+            let imbalance = (demand - supply).abs();
+            let better = match best {
+                Some((_, best_volume, best_imbalance)) => {
+                    volume > best_volume || (volume == best_volume && imbalance < best_imbalance)
+                }
+                None => volume > Qty::ZERO,
+            };
+            if better {
+                best = Some((price, volume, imbalance));
+            }
+        }
+        best.map(|(price, _, _)| price)
+    }
+
+    fn record_trades(&mut self, trades: &mut VecDeque<Trade>) {
+        for trade in trades.iter_mut() {
+            self.trade_count += 1;
+            trade.sequence = self.trade_count;
+            self.events.push(EngineEvent::Traded(*trade));
+        }
+        self.traded_notional += trades.iter().map(Trade::notional).sum::<Notional>();
+        self.tape.extend(trades.iter().copied());
+        if let Some(max_tape_len) = self.max_tape_len {
+            let excess = self.tape.len().saturating_sub(max_tape_len);
+            self.tape.drain(..excess);
+        }
+        if !trades.is_empty() {
+            self.expire_trade_ttls();
+            self.trigger_touched(trades);
+        }
+    }
+
+    /// Sends pending market-if-touched orders whose trigger `trades` reached to
+    /// the book as market orders. Their own trades may trigger more.
+    fn trigger_touched(&mut self, trades: &VecDeque<Trade>) {
+        let low = trades.iter().map(|trade| trade.price).min().unwrap();
+        let high = trades.iter().map(|trade| trade.price).max().unwrap();
+        let (touched, waiting): (Vec<Order>, Vec<Order>) = self
+            .pending
+            .drain(..)
+            .partition(|order| order.touched(low, high));
+        self.pending = waiting;
+        for order in touched {
+            self.route(order.trigger(), None);
+        }
+    }
+
+    fn expire_trade_ttls(&mut self) {
+        let trade_count = self.trade_count;
+        let expired = |order: &Order| {
+            order
+                .expires_at_trade
+                .is_some_and(|expires_at_trade| expires_at_trade <= trade_count)
+        };
+        let mut expired_orders = Vec::new();
+        for book in [&mut self.bids, &mut self.asks].iter_mut() {
+            if book.iter().any(expired) {
+                book.retain(|order| {
+                    if expired(order) {
+                        expired_orders.push(*order);
+                    }
+                    !expired(order)
+                });
+            }
+        }
+        if let Some(on_expire) = self.on_expire.as_mut() {
+            expired_orders.iter().for_each(on_expire);
+        }
+    }
+
+    fn next_id(&mut self) -> Result<u64, SubmitError> {
+        let id = self.next_order_id.ok_or(SubmitError::IdsExhausted)?;
+        self.next_order_id = id.checked_add(1);
+        Ok(id)
+    }
+
+    fn next_seq(&mut self) -> u64 {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        seq
+    }
+
+    /// The order's own limits, with `orders_at_level` already resting at its price.
+    fn validate(&self, order: &Order, orders_at_level: usize) -> Result<(), SubmitError> {
+        if order.amount < self.min_order_qty {
+            return Err(SubmitError::BelowMinOrderQty);
+        }
+        if order.amount > self.max_order_qty {
+            return Err(SubmitError::AboveMaxOrderQty);
+        }
+        if let Some((low, high)) = self.price_band {
+            if !order.is_market() && !(low..=high).contains(&order.price) {
+                return Err(SubmitError::OutsidePriceBand);
+            }
+        }
+        if let Some(tick_size) = self.tick_size {
+            if !order.is_market() && order.price % tick_size != 0 {
+                return Err(SubmitError::OffTick);
+            }
+        }
+        if let Some(max_orders_per_level) = self.max_orders_per_level {
+            if !order.is_market() && orders_at_level >= max_orders_per_level {
+                return Err(SubmitError::PriceLevelFull);
+            }
+        }
+        Ok(())
+    }
+
+    /// `validate` against the book as it stands, recording a failure in the
+    /// event stream.
+    fn check_risk(&mut self, order: &Order) -> Result<(), SubmitError> {
+        let orders_at_level = self
+            .book(order.side)
+            .iter()
+            .filter(|other| other.price == order.price)
+            .count();
+        self.check_risk_at(order, orders_at_level)
+    }
+
+    /// `check_risk` with `orders_at_level` resting at the order's price.
+    fn check_risk_at(&mut self, order: &Order, orders_at_level: usize) -> Result<(), SubmitError> {
+        self.validate(order, orders_at_level)
+            .inspect_err(|&reason| {
+                self.events.push(EngineEvent::Rejected {
+                    order_id: order.id,
+                    reason,
+                })
+            })
+    }
+
+    fn validate_quote(&mut self, bid: &mut Order, ask: &mut Order) -> Result<(), SubmitError> {
+        if bid.side != Side::Bid || ask.side != Side::Ask {
+            return Err(SubmitError::InvalidQuoteSides);
+        }
+        if bid.price >= ask.price {
+            return Err(SubmitError::SelfCrossingQuote);
+        }
+        if bid.id != 0 && bid.id == ask.id {
+            return Err(SubmitError::DuplicateOrderId);
+        }
+        self.book_or_cancel(bid)?;
+        self.book_or_cancel(ask)?;
+        self.check_risk(bid)?;
+        self.check_risk(ask)?;
+        self.check_id(bid)?;
+        self.check_id(ask)?;
+        self.check_depth(bid)?;
+        self.check_depth(ask)?;
+        self.check_liquidity(bid)?;
+        self.check_liquidity(ask)
+    }
+
+    /// `BookFull` if whatever `order` leaves unfilled would rest on a full side
+    /// below every order there, see `set_max_depth_per_side`.
+    fn check_depth(&self, order: &Order) -> Result<(), SubmitError> {
+        let max_depth = match self.max_depth_per_side {
+            Some(max_depth) => max_depth,
+            None => return Ok(()),
+        };
+        let book = self.book(order.side);
+        let takes_only = matches!(order.time_in_force, TimeInForce::Ioc | TimeInForce::Fok);
+        if book.len() < max_depth
+            || order.is_market()
+            || takes_only
+            || self.fillable(order) >= order.amount
+        {
+            return Ok(());
+        }
+        match book.iter().min() {
+            Some(worst) if worst > order => Err(SubmitError::BookFull),
+            _ => Ok(()),
+        }
+    }
+
+    /// Everything `mass_quote` needs to hold before it cancels the `stale`
+    /// orders: they may be cancelled, no quote would trade, either against
+    /// another account or against the rest of `quotes`, and each quote would be
+    /// accepted by the book as it will be once the stale orders are gone and
+    /// the quotes before it rest.
+    fn validate_mass_quote(
+        &mut self,
+        account: u64,
+        stale: &[u64],
+        quotes: &[Order],
+    ) -> Result<(), SubmitError> {
+        if let Some(min_rest) = self.min_rest {
+            let now = self.now();
+            let min_rest = self.time_unit.of(min_rest);
+            if stale
+                .iter()
+                .filter_map(|&id| self.find(id))
+                .any(|order| now < order.live_since + min_rest)
+            {
+                return Err(SubmitError::TooSoon);
+            }
+        }
+        let without_stale = |book: &B| -> Vec<Order> {
+            book.iter()
+                .filter(|order| !stale.contains(&order.id))
+                .copied()
+                .collect()
+        };
+        let mut bids = without_stale(&self.bids);
+        let mut asks = without_stale(&self.asks);
+        for (index, quote) in quotes.iter().enumerate() {
+            let (same_side, other_side) = match quote.side {
+                Side::Bid => (&mut bids, &asks),
+                Side::Ask => (&mut asks, &bids),
+            };
+            let crosses = other_side
+                .iter()
+                .any(|order| order.account != Some(account) && quote.matches(order));
+            if crosses {
+                return Err(SubmitError::WouldCross);
+            }
+            if quotes[..index]
+                .iter()
+                .any(|other| other.side != quote.side && quote.matches(other))
+            {
+                return Err(SubmitError::SelfCrossingQuote);
+            }
+            if quote.id != 0 && quotes[..index].iter().any(|other| other.id == quote.id) {
+                return Err(SubmitError::DuplicateOrderId);
+            }
+            if quote.is_market() && self.external_venue.is_none() && other_side.is_empty() {
+                return Err(SubmitError::NoLiquidity);
+            }
+            let orders_at_level = same_side
+                .iter()
+                .filter(|other| other.price == quote.price)
+                .count();
+            self.check_risk_at(quote, orders_at_level)?;
+            // a stale id is free again once it's cancelled, unless ids are never reused
+            if !stale.contains(&quote.id) {
+                self.check_id(quote)?;
+            } else if self.reject_reused_ids {
+                return Err(SubmitError::DuplicateOrderId);
+            }
+            let takes_only = matches!(quote.time_in_force, TimeInForce::Ioc | TimeInForce::Fok);
+            if quote.is_market() || takes_only {
+                continue;
+            }
+            // evicts the worst order like `push_bounded`, unless that's the quote
+            while self
+                .max_depth_per_side
+                .is_some_and(|max_depth| same_side.len() >= max_depth)
+            {
+                let (worst_index, worst) =
+                    match same_side.iter().enumerate().min_by_key(|&(_, order)| order) {
+                        Some(worst) => worst,
+                        None => break,
+                    };
+                if worst > quote {
+                    return Err(SubmitError::BookFull);
+                }
+                same_side.swap_remove(worst_index);
+            }
+            same_side.push(*quote);
+        }
+        Ok(())
+    }
+
+    fn check_id(&self, order: &Order) -> Result<(), SubmitError> {
+        if order.id == 0 {
+            return Ok(());
+        }
+        let live = self.find(order.id).is_some()
+            || self.pending.iter().any(|other| other.id == order.id)
+            || self.auction_queue.iter().any(|other| other.id == order.id)
+            || self
+                .collected_markets
+                .iter()
+                .any(|other| other.id == order.id);
+        if live || (self.reject_reused_ids && self.used_ids.contains(&order.id)) {
+            return Err(SubmitError::DuplicateOrderId);
+        }
+        Ok(())
+    }
+
+    /// A market order needs something to trade against: the opposite side of
+    /// the book, or an external venue to route to.
+    fn check_liquidity(&self, order: &Order) -> Result<(), SubmitError> {
+        let other_side = match order.side {
+            Side::Bid => Side::Ask,
+            Side::Ask => Side::Bid,
+        };
+        if order.is_market() && self.external_venue.is_none() && self.book(other_side).is_empty() {
+            return Err(SubmitError::NoLiquidity);
+        }
+        Ok(())
+    }
+
+    fn execute_order(
+        &mut self,
+        mut new_order: Order,
+        mut trace: Option<&mut Vec<MatchStep>>,
+    ) -> ExecutionReport {
+        if new_order.time_in_force == TimeInForce::Fok
+            && self.fillable(&new_order) < new_order.amount
+        {
+            return ExecutionReport {
+                order_id: new_order.id,
+                status: OrderStatus::Cancelled,
+                trades: VecDeque::new(),
+            };
+        }
+        let trade_count = self.trade_count;
+        let now = self.now();
+        let trade_price_policy = self.trade_price_policy;
+        let mid = self.mid().filter(|_| self.record_maker_edge);
+        let max_depth_per_side = self.max_depth_per_side;
+        let market_remainder_policy = self.market_remainder_policy;
+        let external_venue = self.external_venue.as_deref();
+        let events = &mut self.events;
+        let (same_side, other_side) = if new_order.side == Side::Bid {
+            (&mut self.bids, &mut self.asks)
+        } else {
+            (&mut self.asks, &mut self.bids)
+        };
+        let mut trades = VecDeque::<Trade>::new();
+
+        while new_order.amount > Qty::ZERO {
+            let matched_order = match other_side.peek_best() {
+                Some(matched_order) => matched_order,
+                None => break,
+            };
+            let crossed = new_order.matches(matched_order);
+            if let Some(steps) = trace.as_mut() {
+                steps.push(MatchStep::Peeked {
+                    order_id: matched_order.id,
+                    crossed,
+                });
+            }
+            if !crossed {
+                break;
+            }
+            let matched_amount = min(new_order.amount, matched_order.amount);
+            let matched_order_id = matched_order.id;
+            let price = trade_price_policy.trade_price(&new_order, matched_order);
+            let (buyer_account, seller_account) = match new_order.side {
+                Side::Bid => (new_order.account, matched_order.account),
+                Side::Ask => (matched_order.account, new_order.account),
+            };
+            if let Some(steps) = trace.as_mut() {
+                steps.push(MatchStep::Matched {
+                    order_id: matched_order.id,
+                    amount: matched_amount,
+                    price,
+                });
+            }
+            new_order.amount = decrement(new_order.amount, matched_amount);
+            new_order.filled += matched_amount;
+            // if other order is filled, remove it
+            if matched_amount == matched_order.amount {
+                other_side.pop_best();
+            } else {
+                // otherwise, lower amount only
+                other_side.decrement_best(matched_amount);
+            }
+            trades.push_back(Trade {
+                sequence: 0,
+                executing_order_id: new_order.id,
+                matched_order_id,
+                timestamp: now,
+                amount: matched_amount,
+                price,
+                maker_edge_bps: mid.map(|mid| maker_edge_bps(new_order.side, price, mid)),
+                external: false,
+                buyer_account,
+                seller_account,
+                busted: false,
+            });
+        }
+        if let Some(venue) = external_venue.filter(|_| new_order.amount > Qty::ZERO) {
+            if let Some((price, available)) = venue.quote(new_order.side, new_order.amount) {
+                let amount = min(available, new_order.amount);
+                if amount > Qty::ZERO && new_order.accepts_price(price) {
+                    new_order.amount = decrement(new_order.amount, amount);
+                    new_order.filled += amount;
+                    trades.push_back(Trade {
+                        sequence: 0,
+                        executing_order_id: new_order.id,
+                        matched_order_id: 0,
+                        timestamp: now,
+                        amount,
+                        price,
+                        maker_edge_bps: None,
+                        external: true,
+                        buyer_account: new_order.account.filter(|_| new_order.side == Side::Bid),
+                        seller_account: new_order.account.filter(|_| new_order.side == Side::Ask),
+                        busted: false,
+                    });
+                }
+            }
+        }
+        if new_order.is_market() && new_order.amount > Qty::ZERO {
+            match (market_remainder_policy, trades.back()) {
+                (MarketRemainderPolicy::RestAtLast, Some(last)) => {
+                    new_order = new_order.into_limit(last.price);
+                }
+                _ => {
+                    return ExecutionReport {
+                        order_id: new_order.id,
+                        status: OrderStatus::Cancelled,
+                        trades,
+                    }
+                }
+            }
+        }
+        let takes_only = matches!(new_order.time_in_force, TimeInForce::Ioc | TimeInForce::Fok);
+        if takes_only && new_order.amount > Qty::ZERO {
+            return ExecutionReport {
+                order_id: new_order.id,
+                status: OrderStatus::Cancelled,
+                trades,
+            };
+        }
+        let status = if new_order.amount > Qty::ZERO {
+            new_order.expires_at_trade = new_order
+                .cancel_after_trades
+                .map(|trades_left| trade_count + trades.len() as u64 + trades_left);
+            if let Err(reason) = push_bounded(same_side, new_order, max_depth_per_side, events) {
+                // the remainder is dropped, like an IOC's
+                let status = if trades.is_empty() {
+                    OrderStatus::Rejected(reason)
+                } else {
+                    OrderStatus::Cancelled
+                };
+                return ExecutionReport {
+                    order_id: new_order.id,
+                    status,
+                    trades,
+                };
+            }
+            if let Some(steps) = trace {
+                steps.push(MatchStep::Rested {
+                    remainder: new_order.amount,
+                });
+            }
+            if trades.is_empty() {
+                OrderStatus::Resting
+            } else {
+                OrderStatus::PartiallyFilled
+            }
+        } else {
+            OrderStatus::Filled
+        };
+
+        ExecutionReport {
+            order_id: new_order.id,
+            status,
+            trades,
+        }
+    }
+}
+
+/// How far the maker's price beats the mid, in basis points of the mid; the
+/// maker is on the other side of the `taker` order.
+fn maker_edge_bps(taker: Side, price: PxTicks, mid: f64) -> f64 {
+    let edge = match taker {
+        Side::Bid => price as f64 - mid,
+        Side::Ask => mid - price as f64,
+    };
+    edge / mid * 10_000.0
+}
+
+/// Books a fill against open `(side, price, amount)` lots, which are all on the
+/// same side. Closes lots first-in first-out and opens a new one with whatever
+/// is left over; returns the realized PnL.
+fn fifo_fill(
+    lots: &mut VecDeque<(Side, PxTicks, Qty)>,
+    side: Side,
+    price: PxTicks,
+    amount: Qty,
+) -> Notional {
+    let mut pnl = Notional::ZERO;
+    let mut left = amount;
+    while left > Qty::ZERO {
+        let (_, lot_price, lot_amount) = match lots.front_mut() {
+            Some(lot) if lot.0 != side => lot,
+            _ => break,
+        };
+        let closed = min(left, *lot_amount);
+        let (bought, sold) = match side {
+            Side::Bid => (price, *lot_price),
+            Side::Ask => (*lot_price, price),
+        };
+        pnl += notional(sold, closed) - notional(bought, closed);
+        left -= closed;
+        *lot_amount -= closed;
+        if *lot_amount == Qty::ZERO {
+            lots.pop_front();
+        }
+    }
+    if left > Qty::ZERO {
+        lots.push_back((side, price, left));
+    }
+    pnl
+}
+
+/// Pushes `order`, first evicting the worst-priority orders while the book is
+/// at `max_depth`. An order that would rank below all of them is refused with
+/// `BookFull` instead, and nothing is evicted.
+fn push_bounded<B: Book>(
+    book: &mut B,
+    order: Order,
+    max_depth: Option<usize>,
+    events: &mut EventLog,
+) -> Result<(), SubmitError> {
+    if let Some(max_depth) = max_depth {
+        while book.len() >= max_depth {
+            match book.pop_worst() {
+                Some(worst) if worst > order => {
+                    book.insert(worst);
+                    return Err(SubmitError::BookFull);
+                }
+                Some(worst) => events.push(EngineEvent::Evicted(worst)),
+                None => break,
+            }
+        }
+    }
+    book.insert(order);
+    Ok(())
+}
+
+/// Takes a fill of `amount` off the first of `markets`, or off the best order of
+/// `book` once they're used up.
+fn take_from<B: Book>(markets: &mut VecDeque<Order>, book: &mut B, amount: Qty) {
+    match markets.front_mut() {
+        Some(market) => {
+            market.amount = decrement(market.amount, amount);
+            market.filled += amount;
+            if market.amount == Qty::ZERO {
+                markets.pop_front();
+            }
+        }
+        None => book.decrement_best(amount),
+    }
+}
+
+/// `available - matched`; matching never takes more than an order has left.
+pub(crate) fn decrement(available: Qty, matched: Qty) -> Qty {
+    debug_assert!(
+        (Qty::ZERO..=available).contains(&matched),
+        "matched {} of {} available",
+        matched,
+        available
+    );
+    available
+        .checked_sub(matched)
+        .filter(|&left| left >= Qty::ZERO)
+        .expect("matched amount exceeds available amount")
+}
+
+fn orders_json<B: Book>(book: &B) -> String {
+    let orders: Vec<String> = book
+        .iter_priority()
+        .iter()
+        .map(|order| {
+            format!(
+                "{{\"price\":{},\"amount\":\"{}\",\"timestamp\":{}}}",
+                order.price, order.amount, order.timestamp
+            )
+        })
+        .collect();
+    format!("[{}]", orders.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::HeapBook;
+    use crate::clock::{MockClock, TimeUnit};
+    use crate::orders::{order_from_str, Strategy, TimeInForce};
+    use crate::snapshot::LevelChange;
+    use std::sync::{Arc, Mutex};
+
+    /// Sorted best first; the simplest possible `Book`.
+    #[derive(Default)]
+    struct VecBook(Vec<Order>);
+
+    impl Book for VecBook {
+        fn insert(&mut self, order: Order) {
+            let index = self.0.iter().position(|other| order > *other);
+            self.0.insert(index.unwrap_or(self.0.len()), order);
+        }
+
+        fn peek_best(&self) -> Option<&Order> {
+            self.0.first()
+        }
+
+        fn pop_best(&mut self) -> Option<Order> {
+            if self.0.is_empty() {
+                return None;
+            }
+            Some(self.0.remove(0))
+        }
+
+        fn pop_worst(&mut self) -> Option<Order> {
+            self.0.pop()
+        }
+
+        fn remove_by_id(&mut self, id: u64) -> Option<Order> {
+            let index = self.0.iter().position(|order| order.id == id)?;
+            Some(self.0.remove(index))
+        }
+
+        fn retain<F: FnMut(&Order) -> bool>(&mut self, keep: F) {
+            self.0.retain(keep);
+        }
+
+        fn iter(&self) -> Box<dyn Iterator<Item = &Order> + '_> {
+            Box::new(self.0.iter())
+        }
+
+        fn iter_priority(&self) -> Vec<&Order> {
+            self.0.iter().collect()
+        }
+
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+    }
+
+    fn crossing_scenarios<B: Book>() {
+        let submit = |engine: &mut MatchingEngine<B>, line: &str| {
+            engine.submit(order_from_str(line).unwrap())
+        };
+
+        let mut engine = MatchingEngine::<B>::with_books();
+        submit(&mut engine, "4 10 10 1 0");
+        let report = submit(&mut engine, "8 10 10 3 0");
+        assert_has_one_trade(report.trades, Qty::units(10), 10);
+        assert_eq!(engine.total_order_count(), 0);
+
+        let mut engine = MatchingEngine::<B>::with_books();
+        submit(&mut engine, "8 10 10 1 0");
+        let report = submit(&mut engine, "4 10 10 5 0");
+        assert_has_one_trade(report.trades, Qty::units(10), 10);
+        assert_eq!(engine.total_order_count(), 0);
+
+        let mut engine = MatchingEngine::<B>::with_books();
+        submit(&mut engine, "4 5 12 1 0");
+        submit(&mut engine, "4 5 11 2 0");
+        submit(&mut engine, "8 2 10 3 0");
+        let report = submit(&mut engine, "8 7 12 4 0");
+        assert_eq!(report.consolidated_fills(), levels(&[(11, 5), (12, 2)]));
+        assert_eq!(engine.depth(10), (levels(&[(10, 2)]), levels(&[(12, 3)])));
+
+        let mut engine = MatchingEngine::<B>::with_books();
+        submit(&mut engine, "4 10 10 1 0");
+        submit(&mut engine, "4 10 20 2 0");
+        let report = submit(&mut engine, "8 20 0 3 1");
+        assert_eq!(report.consolidated_fills(), levels(&[(10, 10), (20, 10)]));
+        assert_eq!(engine.total_order_count(), 0);
+    }
+
+    #[test]
+    fn test_crossing_on_other_book_backends() {
+        crossing_scenarios::<VecBook>();
+        crossing_scenarios::<crate::book::BTreeBook>();
+    }
+
+    #[test]
+    fn test_cross_order_bid() {
+        let mut engine = MatchingEngine::<HeapBook>::with_books();
+        engine.submit(order_from_str("4 10 10 1 0").unwrap());
+
+        let report = engine.submit(order_from_str("8 10 10 3 0").unwrap());
+
+        assert_eq!(engine.asks.into_sorted_vec(), []);
+        assert_eq!(engine.bids.into_sorted_vec(), []);
+        assert_has_one_trade(report.trades, Qty::units(10), 10);
+    }
+
+    #[test]
+    fn test_trade_carries_both_order_ids() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("4 10 10 1 0").unwrap());
+        let report = engine.submit(order_from_str("8 10 10 3 0").unwrap());
+
+        let trade = report.trades[0];
+        assert_eq!((trade.executing_order_id, trade.matched_order_id), (2, 1));
+
+        engine.submit(order_from_str("7 4 5 10 4 0").unwrap());
+        let report = engine.submit(order_from_str("9 8 5 10 5 0").unwrap());
+        assert_eq!(report.trades[0].executing_order_id, 9);
+        assert_eq!(report.trades[0].matched_order_id, 7);
+    }
+
+    #[test]
+    fn test_cross_order_ask() {
+        let mut engine = MatchingEngine::<HeapBook>::with_books();
+        engine.submit(order_from_str("8 10 10 1 0").unwrap());
+
+        let report = engine.submit(order_from_str("4 10 10 5 0").unwrap());
+
+        assert_eq!(engine.asks.into_sorted_vec(), []);
+        assert_eq!(engine.bids.into_sorted_vec(), []);
+        assert_has_one_trade(report.trades, Qty::units(10), 10);
+    }
+
+    #[test]
+    fn test_into_sorted_vec_ends_with_best_bid() {
+        let mut engine = MatchingEngine::<HeapBook>::with_books();
+        for line in &["8 1 5 1 0", "8 1 10 2 0", "8 1 7 3 0"] {
+            engine.submit(order_from_str(line).unwrap());
+        }
+
+        let prices: Vec<PxTicks> = engine
+            .bids
+            .into_sorted_vec()
+            .iter()
+            .map(|order| order.price)
+            .collect();
+
+        assert_eq!(prices, vec![5, 7, 10]);
+    }
+
+    fn pop_order(mut book: HeapBook) -> Vec<(PxTicks, i32)> {
+        std::iter::from_fn(|| book.pop())
+            .map(|order| (order.price, order.timestamp))
+            .collect()
+    }
+
+    #[test]
+    fn test_asks_pop_lowest_price_first_then_earliest() {
+        let mut engine = MatchingEngine::<HeapBook>::with_books();
+        for line in &["4 1 12 1 0", "4 1 10 3 0", "4 1 11 2 0", "4 1 10 2 0"] {
+            engine.submit(order_from_str(line).unwrap());
+        }
+
+        assert_eq!(engine.asks.peek().unwrap().price, 10);
+        assert_eq!(
+            pop_order(engine.asks),
+            vec![(10, 2), (10, 3), (11, 2), (12, 1)]
+        );
+    }
+
+    #[test]
+    fn test_bids_pop_highest_price_first_then_earliest() {
+        let mut engine = MatchingEngine::<HeapBook>::with_books();
+        for line in &["8 1 10 1 0", "8 1 12 3 0", "8 1 11 2 0", "8 1 12 2 0"] {
+            engine.submit(order_from_str(line).unwrap());
+        }
+
+        assert_eq!(engine.bids.peek().unwrap().price, 12);
+        assert_eq!(
+            pop_order(engine.bids),
+            vec![(12, 2), (12, 3), (11, 2), (10, 1)]
+        );
+    }
+
+    #[test]
+    fn test_cheaper_ask_comes_in() {
+        let mut engine = MatchingEngine::<HeapBook>::with_books();
+        engine.submit(order_from_str("8 10 10 1 0").unwrap());
+
+        let report = engine.submit(order_from_str("4 10 5 3 0").unwrap());
+
+        assert_eq!(engine.asks.into_sorted_vec(), []);
+        assert_eq!(engine.bids.into_sorted_vec(), []);
+        assert_has_one_trade(report.trades, Qty::units(10), 10);
+    }
+
+    #[test]
+    fn test_market_order_buys_all() {
+        let mut engine = MatchingEngine::<HeapBook>::with_books();
+        engine.asks = HeapBook::from(vec![
+            order_from_str("4 10 10 1 0").unwrap(),
+            order_from_str("4 10 20 1 0").unwrap(),
+        ]);
+
+        let report = engine.submit(order_from_str("8 20 0 3 1").unwrap());
+
+        assert_eq!(engine.asks.into_sorted_vec(), []);
+        assert_eq!(engine.bids.into_sorted_vec(), []);
+        assert_eq!(report.trades.front().unwrap().amount, Qty::units(10));
+        assert_eq!(report.trades.front().unwrap().price, 10);
+        assert_eq!(report.trades.get(1).unwrap().amount, Qty::units(10));
+        assert_eq!(report.trades.get(1).unwrap().price, 20);
+    }
+
+    #[test]
+    fn test_market_bid_sweeps_asks_at_resting_prices() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("4 2 11 1 0").unwrap());
+        engine.submit(order_from_str("4 3 12 2 0").unwrap());
+        engine.submit(order_from_str("4 4 13 3 0").unwrap());
+
+        let report = engine.submit(order_from_str("8 6 0 4 1").unwrap());
+
+        assert_eq!(
+            report.consolidated_fills(),
+            levels(&[(11, 2), (12, 3), (13, 1)])
+        );
+        assert_eq!(engine.depth(10).1, levels(&[(13, 3)]));
+    }
+
+    #[test]
+    fn test_market_ask_sweeps_bids_at_resting_prices() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("8 2 13 1 0").unwrap());
+        engine.submit(order_from_str("8 3 12 2 0").unwrap());
+        engine.submit(order_from_str("8 4 11 3 0").unwrap());
+
+        let report = engine.submit(order_from_str("4 6 0 4 1").unwrap());
+
+        assert_eq!(
+            report.consolidated_fills(),
+            levels(&[(13, 2), (12, 3), (11, 1)])
+        );
+        assert_eq!(engine.depth(10).0, levels(&[(11, 3)]));
+    }
+
+    #[test]
+    fn test_oversized_market_order_remainder_cancelled_by_default() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("4 2 11 1 0").unwrap());
+        engine.submit(order_from_str("4 3 12 2 0").unwrap());
+
+        let report = engine.submit(order_from_str("8 9 0 3 1").unwrap());
+
+        assert_eq!(report.status, OrderStatus::Cancelled);
+        assert_eq!(report.consolidated_fills(), levels(&[(11, 2), (12, 3)]));
+        assert_eq!(engine.total_order_count(), 0);
+    }
+
+    #[test]
+    fn test_market_buy_walks_asks_at_their_prices() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("4 2 5 1 0").unwrap());
+        engine.submit(order_from_str("4 3 7 2 0").unwrap());
+
+        let report = engine.submit(order_from_str("8 5 0 3 1").unwrap());
+
+        assert_eq!(report.status, OrderStatus::Filled);
+        let fills: Vec<_> = report.trades.iter().map(|t| (t.price, t.amount)).collect();
+        assert_eq!(fills, levels(&[(5, 2), (7, 3)]));
+        assert_eq!(engine.total_order_count(), 0);
+    }
+
+    #[test]
+    fn test_market_order_against_empty_side_rejected() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("8 2 5 1 0").unwrap());
+
+        let report = engine.submit(order_from_str("8 5 0 2 1").unwrap());
+
+        assert_eq!(
+            report.status,
+            OrderStatus::Rejected(SubmitError::NoLiquidity)
+        );
+        assert!(report.trades.is_empty());
+        assert_eq!(engine.depth(10), (levels(&[(5, 2)]), vec![]));
+        assert_eq!(SubmitError::NoLiquidity.code(), "no_liquidity");
+    }
+
+    #[test]
+    fn test_oversized_market_order_rests_at_last_fill_price() {
+        let mut engine = MatchingEngine::new();
+        engine.set_market_remainder_policy(MarketRemainderPolicy::RestAtLast);
+        engine.submit(order_from_str("4 2 11 1 0").unwrap());
+        engine.submit(order_from_str("4 3 12 2 0").unwrap());
+
+        let report = engine.submit(order_from_str("8 9 0 3 1").unwrap());
+
+        assert_eq!(report.status, OrderStatus::PartiallyFilled);
+        assert_eq!(engine.depth(10).0, levels(&[(12, 4)]));
+        assert!(!engine.bids().next().unwrap().is_market());
+        let report = engine.submit(order_from_str("4 1 12 4 0").unwrap());
+        assert_has_one_trade(report.trades, Qty::units(1), 12);
+    }
+
+    #[test]
+    fn test_fok_that_cannot_fill_leaves_book_untouched() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("4 3 10 1 0").unwrap());
+        engine.submit(order_from_str("4 3 11 2 0").unwrap());
+        engine.submit(order_from_str("4 3 13 3 0").unwrap());
+
+        let report = engine.submit(order_from_str("8 7 12 4 0 fok").unwrap());
+
+        assert_eq!(report.status, OrderStatus::Cancelled);
+        assert!(report.trades.is_empty());
+        assert_eq!(engine.depth(10).1, levels(&[(10, 3), (11, 3), (13, 3)]));
+        assert!(engine.depth(10).0.is_empty());
+    }
+
+    #[test]
+    fn test_fok_that_exactly_fills() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("4 3 10 1 0").unwrap());
+        engine.submit(order_from_str("4 3 11 2 0").unwrap());
+
+        let report = engine.submit(order_from_str("8 6 11 3 0 fok").unwrap());
+
+        assert_eq!(report.status, OrderStatus::Filled);
+        assert_eq!(report.consolidated_fills(), levels(&[(10, 3), (11, 3)]));
+        assert_eq!(engine.total_order_count(), 0);
+    }
+
+    #[test]
+    fn test_ioc_drops_unfilled_remainder() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("4 3 10 1 0").unwrap());
+        engine.submit(order_from_str("4 3 12 2 0").unwrap());
+
+        let report = engine.submit(order_from_str("8 5 11 3 0 ioc").unwrap());
+
+        assert_eq!(report.status, OrderStatus::Cancelled);
+        assert_has_one_trade(report.trades, Qty::units(3), 10);
+        assert!(engine.depth(10).0.is_empty());
+        assert_eq!(engine.depth(10).1, levels(&[(12, 3)]));
+    }
+
+    #[test]
+    fn test_fractional_quantities_trade_exactly() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("4 0.5 10 1 0").unwrap());
+
+        let report = engine.submit(order_from_str("8 0.3 10 2 0").unwrap());
+
+        assert_has_one_trade(report.trades, "0.3".parse().unwrap(), 10);
+        assert_eq!(engine.depth(1).1, vec![(10, "0.2".parse().unwrap())]);
+        assert_eq!(engine.traded_notional, Notional::ticks(3));
+    }
+
+    #[test]
+    fn test_price_priority_dominates_time_across_levels() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("4 5 10 1 0").unwrap());
+        engine.submit(order_from_str("4 5 9 2 0").unwrap());
+
+        let report = engine.submit(order_from_str("8 5 11 3 0").unwrap());
+
+        assert_eq!(engine.match_priority(), MatchPriority::PriceTime);
+        assert_has_one_trade(report.trades, Qty::units(5), 9);
+        assert_eq!(engine.depth(10).1, levels(&[(10, 5)]));
+    }
+
+    #[test]
+    fn test_older_resting_order_fills_first_at_equal_price_and_time() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("8 5 10 1 0").unwrap());
+        engine.submit(order_from_str("8 5 10 1 0").unwrap());
+
+        let report = engine.submit(order_from_str("4 5 10 1 0").unwrap());
+
+        assert_eq!(report.status, OrderStatus::Filled);
+        assert_has_one_trade(report.trades, Qty::units(5), 10);
+        assert_eq!(engine.queue_position(1), None);
+        assert_eq!(engine.queue_position(2), Some((0, Qty::ZERO)));
+        assert_eq!(engine.depth(1).0, levels(&[(10, 5)]));
+    }
+
+    #[test]
+    fn test_decrement_within_available() {
+        assert_eq!(decrement(Qty::units(10), Qty::units(4)), Qty::units(6));
+        assert_eq!(decrement(Qty::units(10), Qty::units(10)), Qty::ZERO);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_decrement_more_than_available_panics() {
+        decrement(Qty::units(5), Qty::units(6));
+    }
+
+    #[test]
+    fn test_consolidated_fills_group_by_price() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("4 2 10 1 0").unwrap());
+        engine.submit(order_from_str("4 3 10 2 0").unwrap());
+        engine.submit(order_from_str("4 4 10 3 0").unwrap());
+        engine.submit(order_from_str("4 5 11 4 0").unwrap());
+
+        let report = engine.submit(order_from_str("8 10 11 5 0").unwrap());
+
+        assert_eq!(report.trades.len(), 4);
+        assert_eq!(report.consolidated_fills(), levels(&[(10, 9), (11, 1)]));
+    }
+
+    #[test]
+    fn test_spoofed_client_timestamp_ignored_when_engine_assigned() {
+        let first_filled = |policy: TimestampPolicy| {
+            let mut engine = MatchingEngine::new();
+            engine.set_timestamp_policy(policy);
+            engine.submit(order_from_str("8 5 10 100 0").unwrap());
+            engine.submit(order_from_str("8 5 10 1 0").unwrap());
+            engine.submit(order_from_str("4 5 10 200 0").unwrap());
+            [1, 2]
+                .iter()
+                .copied()
+                .find(|&id| engine.queue_position(id).is_none())
+        };
+
+        assert_eq!(first_filled(TimestampPolicy::ClientSupplied), Some(2));
+        assert_eq!(first_filled(TimestampPolicy::EngineAssigned), Some(1));
+    }
+
+    #[test]
+    fn test_take_then_rest_sweeps_once_and_rests_remainder() {
+        let mut engine = MatchingEngine::<HeapBook>::with_books();
+        engine.submit(order_from_str("4 5 10 1 0").unwrap());
+        engine.submit(order_from_str("4 5 11 2 0").unwrap());
+        engine.submit(order_from_str("4 5 13 3 0").unwrap());
+        let new_order = order_from_str("8 20 12 4 0").unwrap();
+        assert_eq!(new_order.time_in_force, TimeInForce::TAKE_THEN_REST);
+
+        let report = engine.submit(new_order);
+
+        assert_eq!(report.status, OrderStatus::PartiallyFilled);
+        assert_eq!(report.trades.len(), 2);
+        assert_eq!(report.trades[0].price, 10);
+        assert_eq!(report.trades[1].price, 11);
+        let resting_bid = engine.bids.peek().unwrap();
+        assert_eq!(
+            (resting_bid.price, resting_bid.amount),
+            (12, Qty::units(10))
+        );
+        let best_ask = engine.asks.peek().unwrap();
+        assert_eq!((best_ask.price, best_ask.amount), (13, Qty::units(5)));
+    }
+
+    #[test]
+    fn test_traded_notional_accumulates_without_overflow() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("4 1000000 1000000 1 0").unwrap());
+        engine.submit(order_from_str("8 1000000 1000000 2 0").unwrap());
+
+        assert_eq!(engine.traded_notional, Notional::ticks(1_000_000_000_000));
+    }
+
+    #[test]
+    fn test_order_counts_per_side() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("8 5 9 1 0").unwrap());
+        engine.submit(order_from_str("8 3 10 2 0").unwrap());
+        engine.submit(order_from_str("4 2 11 3 0").unwrap());
+        assert_eq!(engine.order_count(Side::Bid), 2);
+        assert_eq!(engine.order_count(Side::Ask), 1);
+        assert_eq!(engine.total_order_count(), 3);
+
+        engine.submit(order_from_str("4 3 10 4 0").unwrap());
+
+        assert_eq!(engine.order_count(Side::Bid), 1);
+        assert_eq!(engine.order_count(Side::Ask), 1);
+        assert_eq!(engine.total_order_count(), 2);
+    }
+
+    #[test]
+    fn test_depth_aggregates_levels() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("8 5 9 1 0").unwrap());
+        engine.submit(order_from_str("8 3 10 2 0").unwrap());
+        engine.submit(order_from_str("8 2 9 3 0").unwrap());
+        engine.submit(order_from_str("8 1 8 4 0").unwrap());
+
+        let (bids, asks) = engine.depth(2);
+
+        assert_eq!(bids, levels(&[(10, 3), (9, 7)]));
+        assert_eq!(asks, vec![]);
+    }
+
+    #[test]
+    fn test_book_snapshot_is_best_first() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("8 5 9 1 0").unwrap());
+        engine.submit(order_from_str("8 3 10 2 0").unwrap());
+        engine.submit(order_from_str("4 2 12 3 0").unwrap());
+        engine.submit(order_from_str("4 1 11 4 0").unwrap());
+
+        let (bids, asks) = engine.book_snapshot();
+
+        let prices = |orders: &[Order]| orders.iter().map(|o| o.price).collect::<Vec<_>>();
+        assert_eq!(prices(&bids), vec![10, 9]);
+        assert_eq!(prices(&asks), vec![11, 12]);
+        assert_eq!(asks[0].strategy(), Strategy::Limit);
+    }
+
+    #[test]
+    fn test_depth_grouped_buckets_levels() {
+        let mut engine = MatchingEngine::new();
+        for line in &[
+            "8 1 13 1 0",
+            "8 2 12 2 0",
+            "8 4 9 3 0",
+            "4 3 16 4 0",
+            "4 5 20 5 0",
+        ] {
+            engine.submit(order_from_str(line).unwrap());
+        }
+
+        let group = |ticks: u64| NonZeroU64::new(ticks).unwrap();
+        assert_eq!(
+            engine.depth_grouped(10, group(5)),
+            (levels(&[(10, 3), (5, 4)]), levels(&[(20, 8)]))
+        );
+        assert_eq!(engine.depth_grouped(1, group(5)).0, levels(&[(10, 3)]));
+        assert_eq!(
+            engine.depth_grouped(10, group(1)),
+            (
+                levels(&[(13, 1), (12, 2), (9, 4)]),
+                levels(&[(16, 3), (20, 5)])
+            )
+        );
+        assert_eq!(
+            engine.depth_grouped(10, group(u64::MAX)),
+            (levels(&[(0, 7)]), vec![(PxTicks::MAX, Qty::units(8))])
+        );
+    }
+
+    #[test]
+    fn test_cost_to_fill_sweeps_opposite_side() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("4 5 10 1 0").unwrap());
+        engine.submit(order_from_str("4 10 11 2 0").unwrap());
+
+        assert_eq!(
+            engine.cost_to_fill(Side::Bid, Qty::units(12)),
+            Some((Notional::ticks(127), 11))
+        );
+        assert_eq!(engine.cost_to_fill(Side::Bid, Qty::units(16)), None);
+        assert_eq!(engine.cost_to_fill(Side::Ask, Qty::units(1)), None);
+        assert_eq!(engine.depth(10).1, levels(&[(10, 5), (11, 10)]));
+    }
+
+    #[test]
+    fn test_order_trades_of_order_filled_in_two_pieces() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("4 5 10 1 0").unwrap());
+        engine.submit(order_from_str("8 2 10 2 0").unwrap());
+        engine.submit(order_from_str("8 1 9 3 0").unwrap());
+        engine.submit(order_from_str("8 3 10 4 0").unwrap());
+
+        let trades = engine.order_trades(1);
+
+        assert_eq!(trades.len(), 2);
+        assert_eq!(
+            trades
+                .iter()
+                .map(|trade| trade.executing_order_id)
+                .collect::<Vec<_>>(),
+            vec![2, 4]
+        );
+        assert!(trades.iter().all(|trade| trade.matched_order_id == 1));
+        assert_eq!(engine.order_trades(4).len(), 1);
+        assert!(engine.order_trades(3).is_empty());
+    }
+
+    #[test]
+    fn test_level_stats_include_traded_volume() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("4 5 10 1 0").unwrap());
+        engine.submit(order_from_str("4 4 10 2 0").unwrap());
+        engine.submit(order_from_str("8 2 10 3 0").unwrap());
+        engine.submit(order_from_str("8 3 10 4 0").unwrap());
+
+        assert_eq!(
+            engine.level_stats(Side::Ask, 10),
+            Some(LevelStats {
+                resting: Qty::units(4),
+                order_count: 1,
+                traded: Qty::units(5),
+            })
+        );
+        assert_eq!(
+            engine
+                .level_stats(Side::Bid, 10)
+                .map(|stats| stats.order_count),
+            Some(0)
+        );
+        assert_eq!(engine.level_stats(Side::Ask, 11), None);
+    }
+
+    #[test]
+    fn test_market_impact_of_multi_level_sweep() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("8 5 96 1 0").unwrap());
+        engine.submit(order_from_str("4 2 100 2 0").unwrap());
+        engine.submit(order_from_str("4 3 100 3 0").unwrap());
+        engine.submit(order_from_str("4 5 110 4 0").unwrap());
+
+        let impact = engine.market_impact(Side::Bid, Qty::units(10)).unwrap();
+
+        assert_eq!(impact.avg_price, 105.0);
+        assert_eq!(impact.worst_price, 110);
+        assert_eq!(impact.levels_consumed, 2);
+        assert!((impact.slippage_bps - 714.2857).abs() < 1e-3);
+        assert_eq!(engine.market_impact(Side::Bid, Qty::units(11)), None);
+        assert_eq!(engine.depth(10).1, levels(&[(100, 5), (110, 5)]));
+    }
+
+    #[test]
+    fn test_would_be_taker_peeks_opposite_top() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("4 5 10 1 0").unwrap());
+        engine.submit(order_from_str("8 5 8 2 0").unwrap());
+
+        assert!(engine.would_be_taker(&order_from_str("8 1 10 3 0").unwrap()));
+        assert!(engine.would_be_taker(&order_from_str("4 1 0 3 1").unwrap()));
+        assert!(!engine.would_be_taker(&order_from_str("8 1 9 3 0").unwrap()));
+        assert!(!engine.would_be_taker(&order_from_str("4 1 9 3 0").unwrap()));
+        assert_eq!(engine.depth(10), (levels(&[(8, 5)]), levels(&[(10, 5)])));
+    }
+
+    #[test]
+    fn test_queue_position_within_level() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("8 5 10 1 0").unwrap());
+        engine.submit(order_from_str("8 3 10 2 0").unwrap());
+        engine.submit(order_from_str("8 7 10 3 0").unwrap());
+        engine.submit(order_from_str("8 9 11 4 0").unwrap());
+
+        assert_eq!(engine.queue_position(1), Some((0, Qty::ZERO)));
+        assert_eq!(engine.queue_position(2), Some((1, Qty::units(5))));
+        assert_eq!(engine.queue_position(3), Some((2, Qty::units(8))));
+        assert_eq!(engine.queue_position(4), Some((0, Qty::ZERO)));
+        assert_eq!(engine.queue_position(5), None);
+    }
+
+    #[test]
+    fn test_snapshot_diff_shows_reduced_level() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("4 10 12 1 0").unwrap());
+        engine.submit(order_from_str("4 10 13 2 0").unwrap());
+        engine.submit(order_from_str("8 5 9 3 0").unwrap());
+        let before = engine.snapshot();
+
+        engine.submit(order_from_str("8 4 12 4 0").unwrap());
+        let diff = before.diff(&engine.snapshot());
+
+        assert_eq!(
+            diff.changes,
+            vec![LevelChange::Changed {
+                side: Side::Ask,
+                price: 12,
+                from: Qty::units(10),
+                to: Qty::units(6),
+            }]
+        );
+        assert!(engine.snapshot().diff(&engine.snapshot()).is_empty());
+    }
+
+    #[test]
+    fn test_fill_estimate_from_queue_and_recent_volume() {
+        let clock = MockClock::new(10_000_000_000);
+        let mut engine = MatchingEngine::new();
+        engine.set_clock(Box::new(clock));
+        engine.submit(order_from_str("8 5 10 1 0").unwrap());
+        engine.submit(order_from_str("8 5 10 2 0").unwrap());
+        for &(timestamp, units) in
+            [(8_000_000_000, 50), (9_200_000_000, 1), (9_900_000_000, 3)].iter()
+        {
+            engine.tape.push(Trade {
+                sequence: 0,
+                executing_order_id: 1,
+                matched_order_id: 1,
+                timestamp,
+                amount: Qty::units(units),
+                price: 10,
+                maker_edge_bps: None,
+                external: false,
+                buyer_account: None,
+                seller_account: None,
+                busted: false,
+            });
+        }
+
+        let lookback = Duration::from_secs(1);
+
+        assert_eq!(engine.fill_estimate(1, lookback), Some(0.8));
+        assert_eq!(engine.fill_estimate(2, lookback), Some(0.4));
+        assert_eq!(engine.fill_estimate(2, Duration::from_secs(5)), Some(1.0));
+        assert_eq!(engine.fill_estimate(3, lookback), None);
+    }
+
+    fn integrity_fixture() -> MatchingEngine {
+        let mut engine = MatchingEngine::new();
+        for line in &[
+            "8 5 10 1 0",
+            "8 5 9 2 0",
+            "4 5 12 3 0",
+            "4 2 10 4 0",
+            "8 1 12 5 0",
+        ] {
+            engine.submit(order_from_str(line).unwrap());
+        }
+        assert_eq!(engine.validate_integrity(), Ok(()));
+        engine
+    }
+
+    #[test]
+    fn test_integrity_detects_crossed_book() {
+        let mut engine = integrity_fixture();
+        engine
+            .bids
+            .insert(Order::limit(Side::Bid, Qty::units(1), 13, 9));
+
+        assert_eq!(
+            engine.validate_integrity(),
+            Err(IntegrityError::CrossedBook { bid: 13, ask: 12 })
+        );
+        engine.set_collecting(true);
+        assert!(!matches!(
+            engine.validate_integrity(),
+            Err(IntegrityError::CrossedBook { .. })
+        ));
+    }
+
+    #[test]
+    fn test_integrity_detects_broken_orders() {
+        let mut engine = integrity_fixture();
+        let mut empty = engine.asks.pop_best().unwrap();
+        empty.amount = Qty::ZERO;
+        engine.asks.insert(empty);
+        assert_eq!(
+            engine.validate_integrity(),
+            Err(IntegrityError::NonPositiveAmount { order_id: 3 })
+        );
+
+        let mut engine = integrity_fixture();
+        let mut duplicate = engine.bids.peek_best().copied().unwrap();
+        duplicate.price = 8;
+        duplicate.seq = engine.next_seq();
+        engine.bids.insert(duplicate);
+        assert_eq!(
+            engine.validate_integrity(),
+            Err(IntegrityError::DuplicateOrderId {
+                order_id: duplicate.id
+            })
+        );
+
+        let mut engine = integrity_fixture();
+        let mut unissued = Order::limit(Side::Bid, Qty::units(1), 8, 9);
+        unissued.id = 99;
+        unissued.seq = engine.next_seq + 1;
+        engine.bids.insert(unissued);
+        assert_eq!(
+            engine.validate_integrity(),
+            Err(IntegrityError::InconsistentSeq { order_id: 99 })
+        );
+    }
+
+    #[test]
+    fn test_integrity_detects_tape_out_of_order() {
+        let mut engine = integrity_fixture();
+        let mut replayed = engine.trades()[0];
+        replayed.sequence = 1;
+        engine.tape.push(replayed);
+
+        assert_eq!(
+            engine.validate_integrity(),
+            Err(IntegrityError::TapeOutOfOrder { sequence: 1 })
+        );
+    }
+
+    #[test]
+    fn test_seed_from_depth_reproduces_depth() {
+        let mut engine = MatchingEngine::new();
+        let bids = levels(&[(10, 5), (9, 7), (8, 1)]);
+        let asks = levels(&[(11, 2), (12, 4)]);
+
+        assert_eq!(engine.seed_from_depth(&bids, &asks), Ok(()));
+
+        assert_eq!(engine.depth(10), (bids, asks));
+        let mut ids: Vec<u64> = engine.bids().chain(engine.asks()).map(|o| o.id).collect();
+        ids.sort_unstable();
+        assert_eq!(ids, vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_seed_from_depth_rejects_crossed_seed() {
+        let mut engine = MatchingEngine::new();
+
+        let result = engine.seed_from_depth(&levels(&[(11, 5)]), &levels(&[(11, 2)]));
+
+        assert_eq!(result, Err(SeedError::Crossed));
+        assert_eq!(engine.depth(10), (vec![], vec![]));
+    }
+
+    #[test]
+    fn test_order_cancelled_after_trade_ttl() {
+        let mut engine = MatchingEngine::new();
+        let mut order = order_from_str("8 10 5 1 0").unwrap();
+        order.cancel_after_trades = Some(2);
+        engine.submit(order);
+        engine.submit(order_from_str("4 10 20 2 0").unwrap());
+
+        engine.submit(order_from_str("8 1 20 3 0").unwrap());
+        assert_eq!(engine.depth(10).0, levels(&[(5, 10)]));
+
+        engine.submit(order_from_str("8 1 20 4 0").unwrap());
+        assert_eq!(engine.depth(10).0, vec![]);
+        assert_eq!(engine.depth(10).1, levels(&[(20, 8)]));
+    }
+
+    #[test]
+    fn test_trade_ttl_applies_to_collected_and_auctioned_orders() {
+        let ttl_order = || {
+            let mut order = order_from_str("8 10 5 1 0").unwrap();
+            order.cancel_after_trades = Some(1);
+            order
+        };
+        let mut collecting = MatchingEngine::new();
+        collecting.set_collecting(true);
+        let clock = MockClock::new(1_000);
+        let mut auction = MatchingEngine::new();
+        auction.set_clock(Box::new(clock.clone()));
+        auction.set_auction_window(Duration::from_secs(1));
+        for engine in [&mut collecting, &mut auction].iter_mut() {
+            engine.submit(ttl_order());
+            engine.submit(order_from_str("4 1 20 2 0").unwrap());
+            engine.submit(order_from_str("8 1 20 3 0").unwrap());
+        }
+
+        assert_eq!(collecting.uncross().len(), 1);
+        assert_eq!(auction.tick(1_000 + 1_000_000_000).len(), 1);
+        assert_eq!(collecting.depth(10), (vec![], vec![]));
+        assert_eq!(auction.depth(10), (vec![], vec![]));
+    }
+
+    #[test]
+    fn test_on_expire_called_with_expired_order() {
+        let expired = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&expired);
+        let mut engine = MatchingEngine::new();
+        engine.set_on_expire(Some(Box::new(move |order: &Order| {
+            recorded.lock().unwrap().push(order.id)
+        })));
+        let mut order = order_from_str("8 10 5 1 0").unwrap();
+        order.id = 42;
+        order.cancel_after_trades = Some(1);
+        engine.submit(order);
+        engine.submit(order_from_str("8 10 4 2 0").unwrap());
+        engine.submit(order_from_str("4 10 20 3 0").unwrap());
+
+        engine.submit(order_from_str("8 1 20 4 0").unwrap());
+
+        assert_eq!(*expired.lock().unwrap(), vec![42]);
+        assert_eq!(engine.depth(10).0, levels(&[(4, 10)]));
+    }
+
+    #[test]
+    fn test_batch_auction_clears_at_one_price_on_tick() {
+        let clock = MockClock::new(1_000);
+        let mut engine = MatchingEngine::new();
+        engine.set_clock(Box::new(clock.clone()));
+        engine.set_auction_window(Duration::from_secs(1));
+
+        for line in ["8 10 11 1 0", "4 5 9 2 0", "4 8 10 3 0", "8 5 10 4 0"].iter() {
+            let report = engine.submit(order_from_str(line).unwrap());
+            assert_eq!(report.status, OrderStatus::Queued);
+        }
+        assert!(engine.tick(1_000 + 999_999_999).is_empty());
+        assert_eq!(engine.depth(10), (vec![], vec![]));
+
+        let trades = engine.tick(1_000 + 1_000_000_000);
+
+        assert_eq!(trades.len(), 3);
+        assert!(trades.iter().all(|trade| trade.price == 10));
+        assert_eq!(
+            trades.iter().map(|trade| trade.amount).sum::<Qty>(),
+            Qty::units(13)
+        );
+        assert_eq!(engine.depth(10), (levels(&[(10, 2)]), vec![]));
+    }
+
+    #[test]
+    fn test_repriced_amend_waits_for_the_auction() {
+        let clock = MockClock::new(1_000);
+        let mut engine = MatchingEngine::new();
+        engine.set_clock(Box::new(clock.clone()));
+        engine.submit(order_from_str("4 5 11 1 0").unwrap());
+        engine.submit(order_from_str("8 5 10 2 0").unwrap());
+        engine.set_auction_window(Duration::from_secs(1));
+
+        let report = engine.amend(2, Qty::units(5), 11).unwrap();
+
+        assert_eq!(report.status, OrderStatus::Queued);
+        assert!(engine.trades().is_empty());
+        let trades = engine.tick(1_000 + 1_000_000_000);
+        assert_has_one_trade(trades, Qty::units(5), 11);
+    }
+
+    #[test]
+    fn test_batch_auction_fills_or_cancels_market_orders() {
+        let clock = MockClock::new(1_000);
+        let mut engine = MatchingEngine::new();
+        engine.set_clock(Box::new(clock.clone()));
+        engine.set_auction_window(Duration::from_secs(1));
+
+        for line in [
+            "8 6 11 1 0",
+            "4 5 10 2 0",
+            "4 5 12 3 0",
+            "8 4 0 4 1",
+            "4 20 0 5 1",
+        ]
+        .iter()
+        {
+            engine.submit(order_from_str(line).unwrap());
+        }
+        let trades = engine.tick(1_000 + 1_000_000_000);
+
+        assert!(trades.iter().all(|trade| trade.price == 10));
+        assert_eq!(trades[0].executing_order_id, 4);
+        assert_eq!(trades[0].matched_order_id, 5);
+        assert_eq!(
+            trades.iter().map(|trade| trade.amount).sum::<Qty>(),
+            Qty::units(10)
+        );
+        assert_eq!(engine.depth(10), (vec![], levels(&[(10, 5), (12, 5)])));
+        let report = engine.submit(order_from_str("8 1 10 6 0").unwrap());
+        assert_eq!(report.status, OrderStatus::Queued);
+    }
+
+    #[test]
+    fn test_collecting_rests_crossing_orders_until_uncross() {
+        let mut engine = MatchingEngine::new();
+        engine.set_collecting(true);
+        engine.submit(order_from_str("8 5 102 1 0").unwrap());
+        engine.submit(order_from_str("8 3 100 2 0").unwrap());
+        let report = engine.submit(order_from_str("4 6 99 3 0").unwrap());
+
+        assert_eq!(report.status, OrderStatus::Resting);
+        assert!(engine.trades().is_empty());
+        assert_eq!(engine.depth(10).1, levels(&[(99, 6)]));
+
+        let trades = engine.uncross();
+
+        assert_eq!(trades.len(), 2);
+        assert!(trades.iter().all(|trade| trade.price == 99));
+        assert_eq!(
+            trades.iter().map(|trade| trade.amount).sum::<Qty>(),
+            Qty::units(6)
+        );
+        assert_eq!(engine.depth(10).0, levels(&[(100, 2)]));
+        let report = engine.submit(order_from_str("4 2 100 4 0").unwrap());
+        assert_has_one_trade(report.trades, Qty::units(2), 100);
+    }
+
+    #[test]
+    fn test_collecting_holds_market_orders_for_uncross() {
+        let mut engine = MatchingEngine::new();
+        engine.set_collecting(true);
+        engine.submit(order_from_str("8 5 102 1 0").unwrap());
+        engine.submit(order_from_str("8 3 100 2 0").unwrap());
+        engine.submit(order_from_str("4 6 99 3 0").unwrap());
+        let report = engine.submit(order_from_str("8 4 0 4 1").unwrap());
+        engine.submit(order_from_str("4 20 0 5 1").unwrap());
+
+        assert_eq!(report.status, OrderStatus::Queued);
+        assert_eq!(engine.depth(10).1, levels(&[(99, 6)]));
+
+        let trades = engine.uncross();
+
+        assert!(trades.iter().all(|trade| trade.price == 99));
+        assert_eq!(
+            (trades[0].executing_order_id, trades[0].matched_order_id),
+            (4, 5)
+        );
+        assert_eq!(
+            trades.iter().map(|trade| trade.amount).sum::<Qty>(),
+            Qty::units(12)
+        );
+        assert_eq!(engine.depth(10), (vec![], levels(&[(99, 6)])));
+        assert_eq!(engine.cancel(5), Err(SubmitError::UnknownOrderId));
+    }
+
+    #[test]
+    fn test_repriced_amend_does_not_match_while_collecting() {
+        let mut engine = MatchingEngine::new();
+        engine.set_collecting(true);
+        engine.submit(order_from_str("8 5 10 1 0").unwrap());
+        engine.submit(order_from_str("4 5 11 2 0").unwrap());
+
+        let report = engine.amend(1, Qty::units(5), 12).unwrap();
+
+        assert_eq!(report.status, OrderStatus::Resting);
+        assert!(engine.trades().is_empty());
+        assert_eq!(engine.depth(10), (levels(&[(12, 5)]), levels(&[(11, 5)])));
+        let trades = engine.uncross();
+        assert_has_one_trade(trades, Qty::units(5), 11);
+    }
+
+    #[test]
+    fn test_clear_keeps_configuration() {
+        let mut engine = MatchingEngine::new();
+        engine.set_size_limits(Qty::units(5), Qty::units(100));
+        engine.submit(order_from_str("4 10 10 1 0").unwrap());
+        engine.submit(order_from_str("8 5 10 2 0").unwrap());
+        engine.submit(order_from_str("8 5 9 3 0").unwrap());
+        assert_eq!(engine.trades().len(), 1);
+
+        engine.clear(true);
+
+        assert_eq!(engine.depth(10), (vec![], vec![]));
+        assert!(engine.trades().is_empty());
+        let report = engine.submit(order_from_str("8 4 10 4 0").unwrap());
+        assert_eq!(
+            report.status,
+            OrderStatus::Rejected(SubmitError::BelowMinOrderQty)
+        );
+        engine.submit(order_from_str("8 5 10 5 0").unwrap());
+        assert_eq!(engine.bids().next().unwrap().id, 1);
+    }
+
+    #[test]
+    fn test_replay_from_save_allocates_same_ids_and_seqs() {
+        let mut engine = MatchingEngine::new();
+        engine.set_clock(Box::new(MockClock::new(1_000)));
+        for line in &["8 5 100 1 0", "4 3 102 2 0", "8 2 101 3 0"] {
+            engine.submit(order_from_str(line).unwrap());
+        }
+        let saved = engine.save();
+        let later = ["4 4 100 4 0", "8 1 98 5 0", "4 1 103 6 0"];
+        for line in &later {
+            engine.submit(order_from_str(line).unwrap());
+        }
+
+        let mut replayed = MatchingEngine::new();
+        replayed.set_clock(Box::new(MockClock::new(1_000)));
+        replayed.load(saved);
+        for line in &later {
+            replayed.submit(order_from_str(line).unwrap());
+        }
+
+        let ids_and_seqs = |engine: &MatchingEngine| {
+            let (mut bids, mut asks) = (engine.bids.iter_priority(), engine.asks.iter_priority());
+            bids.append(&mut asks);
+            bids.iter()
+                .map(|order| (order.id, order.seq))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids_and_seqs(&engine), ids_and_seqs(&replayed));
+        assert_eq!(engine.trades(), replayed.trades());
+        assert_eq!(engine.next_order_id, replayed.next_order_id);
+        assert_eq!(engine.next_seq, replayed.next_seq);
+        assert_eq!(engine.snapshot_json(), replayed.snapshot_json());
+    }
+
+    #[test]
+    fn test_from_orders_matches_in_sequence() {
+        let orders = ["4 5 10 1 0", "4 5 11 2 0", "8 7 11 3 0", "8 2 9 4 0"]
+            .iter()
+            .map(|line| order_from_str(line).unwrap())
+            .collect();
+
+        let engine: MatchingEngine = MatchingEngine::from_orders(orders).unwrap();
+
+        let trades: Vec<(PxTicks, Qty)> = engine
+            .trades()
+            .iter()
+            .map(|t| (t.price, t.amount))
+            .collect();
+        assert_eq!(trades, levels(&[(10, 5), (11, 2)]));
+        assert_eq!(engine.depth(10), (levels(&[(9, 2)]), levels(&[(11, 3)])));
+    }
+
+    #[test]
+    fn test_from_orders_reports_first_invalid_index() {
+        let mut duplicate = order_from_str("8 1 9 2 0").unwrap();
+        duplicate.id = 1;
+        let orders = vec![
+            order_from_str("4 5 10 1 0").unwrap(),
+            duplicate,
+            order_from_str("8 0 9 3 0").unwrap(),
+        ];
+
+        let result: Result<MatchingEngine, _> = MatchingEngine::from_orders(orders);
+
+        assert_eq!(
+            result.err(),
+            Some(BuildError {
+                index: 1,
+                reason: SubmitError::DuplicateOrderId,
+            })
+        );
+    }
+
+    #[test]
+    fn test_price_level_order_cap() {
+        let mut engine = MatchingEngine::new();
+        engine.set_max_orders_per_level(Some(2));
+        engine.submit(order_from_str("8 5 10 1 0").unwrap());
+        engine.submit(order_from_str("8 5 10 2 0").unwrap());
+
+        let over_cap = engine.submit(order_from_str("8 5 10 3 0").unwrap());
+        let other_price = engine.submit(order_from_str("8 5 9 4 0").unwrap());
+
+        assert_eq!(over_cap.error(), Some(SubmitError::PriceLevelFull));
+        assert_eq!(SubmitError::PriceLevelFull.code(), "price_level_full");
+        assert_eq!(other_price.status, OrderStatus::Resting);
+        assert_eq!(engine.depth(10).0, levels(&[(10, 10), (9, 5)]));
+    }
+
+    #[test]
+    fn test_max_depth_evicts_worst_resting_order() {
+        let mut engine = MatchingEngine::new();
+        engine.set_max_depth_per_side(Some(2));
+        engine.submit(order_from_str("8 5 10 1 0").unwrap());
+        engine.submit(order_from_str("8 5 8 2 0").unwrap());
+        engine.submit(order_from_str("4 5 12 3 0").unwrap());
+        assert!(engine.drain_events().is_empty());
+
+        let report = engine.submit(order_from_str("8 5 9 4 0").unwrap());
+
+        assert_eq!(report.status, OrderStatus::Resting);
+        assert_eq!(engine.depth(10).0, levels(&[(10, 5), (9, 5)]));
+        assert_eq!(engine.depth(10).1, levels(&[(12, 5)]));
+        match engine.drain_events().as_slice() {
+            [EngineEvent::Evicted(order)] => assert_eq!((order.id, order.price), (2, 8)),
+            events => panic!("unexpected events {:?}", events),
+        }
+        assert!(engine.drain_events().is_empty());
+    }
+
+    #[test]
+    fn test_max_depth_refuses_an_order_ranking_below_the_book() {
+        let mut engine = MatchingEngine::new();
+        engine.set_max_depth_per_side(Some(2));
+        engine.submit(order_from_str("8 5 10 1 0").unwrap());
+        engine.submit(order_from_str("8 5 9 2 0").unwrap());
+
+        let report = engine.submit(order_from_str("8 5 8 3 0").unwrap());
+
+        assert_eq!(report.error(), Some(SubmitError::BookFull));
+        assert_eq!(engine.depth(10).0, levels(&[(10, 5), (9, 5)]));
+        assert!(engine.drain_events().is_empty());
+    }
+
+    #[test]
+    fn test_max_depth_does_not_evict_when_restoring_orders() {
+        let mut engine = MatchingEngine::new();
+        for line in ["8 5 10 1 0", "8 5 9 2 0", "8 5 8 3 0"].iter() {
+            engine.submit(order_from_str(line).unwrap());
+        }
+        let saved = engine.save();
+        engine.set_max_depth_per_side(Some(2));
+        engine.set_price_band(Some((8, 10)));
+        engine.load(saved);
+
+        assert!(engine.amend(3, Qty::units(5), 12).is_err());
+        assert_eq!(engine.depth(10).0, levels(&[(10, 5), (9, 5), (8, 5)]));
+        assert!(!engine
+            .drain_events()
+            .iter()
+            .any(|event| matches!(event, EngineEvent::Evicted(_))));
+    }
+
+    #[test]
+    fn test_trade_price_policies() {
+        let price_under = |policy: TradePricePolicy, incoming: &str| {
+            let mut engine = MatchingEngine::new();
+            engine.set_trade_price_policy(policy);
+            engine.submit(order_from_str("4 10 10 5 0").unwrap());
+            engine.submit(order_from_str(incoming).unwrap()).trades[0].price
+        };
+
+        assert_eq!(price_under(TradePricePolicy::Resting, "8 10 12 1 0"), 10);
+        assert_eq!(price_under(TradePricePolicy::Incoming, "8 10 12 1 0"), 12);
+        assert_eq!(price_under(TradePricePolicy::Earlier, "8 10 12 1 0"), 12);
+        assert_eq!(price_under(TradePricePolicy::Earlier, "8 10 12 9 0"), 10);
+        assert_eq!(price_under(TradePricePolicy::Incoming, "8 10 0 1 1"), 10);
+    }
+
+    #[test]
+    fn test_maker_edge_against_pre_trade_mid() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("8 5 9 1 0").unwrap());
+        engine.submit(order_from_str("4 5 10 2 0").unwrap());
+        let untracked = engine.submit(order_from_str("4 1 9 3 0").unwrap());
+        assert_eq!(untracked.trades[0].maker_edge_bps, None);
+
+        engine.set_record_maker_edge(true);
+        let report = engine.submit(order_from_str("4 4 9 4 0").unwrap());
+
+        let edge = report.trades[0].maker_edge_bps.unwrap();
+        assert!((edge - 0.5 / 9.5 * 10_000.0).abs() < 1e-9);
+        let one_sided = engine.submit(order_from_str("8 1 10 5 0").unwrap());
+        assert_eq!(one_sided.trades[0].maker_edge_bps, None);
+    }
+
+    /// Offers `available` at `price` to buyers only.
+    struct MockVenue {
+        price: PxTicks,
+        available: Qty,
+    }
+
+    impl ExternalVenue for MockVenue {
+        fn quote(&self, side: Side, qty: Qty) -> Option<(PxTicks, Qty)> {
+            match side {
+                Side::Bid => Some((self.price, min(qty, self.available))),
+                Side::Ask => None,
+            }
+        }
+    }
+
+    #[test]
+    fn test_large_order_fills_partly_on_external_venue() {
+        let mut engine = MatchingEngine::new();
+        engine.set_external_venue(Some(Box::new(MockVenue {
+            price: 11,
+            available: Qty::units(10),
+        })));
+        engine.submit(order_from_str("4 5 10 1 0").unwrap());
+
+        let report = engine.submit(order_from_str("8 20 11 2 0").unwrap());
+
+        let fills: Vec<(PxTicks, Qty, bool)> = report
+            .trades
+            .iter()
+            .map(|trade| (trade.price, trade.amount, trade.external))
+            .collect();
+        assert_eq!(
+            fills,
+            vec![(10, Qty::units(5), false), (11, Qty::units(10), true)]
+        );
+        assert_eq!(report.status, OrderStatus::PartiallyFilled);
+        assert_eq!(engine.depth(10).0, levels(&[(11, 5)]));
+
+        let too_cheap = engine.submit(order_from_str("8 1 10 3 0").unwrap());
+        assert_eq!(too_cheap.status, OrderStatus::Resting);
+    }
+
+    #[test]
+    fn test_fok_counts_the_external_venue() {
+        let mut engine = MatchingEngine::new();
+        engine.set_external_venue(Some(Box::new(MockVenue {
+            price: 11,
+            available: Qty::units(10),
+        })));
+        engine.submit(order_from_str("4 5 10 1 0").unwrap());
+
+        let filled = engine.submit(order_from_str("8 15 11 2 0 FOK").unwrap());
+        assert_eq!(filled.status, OrderStatus::Filled);
+        assert_eq!(
+            filled.consolidated_fills(),
+            vec![(10, Qty::units(5)), (11, Qty::units(10))]
+        );
+
+        engine.submit(order_from_str("4 5 10 3 0").unwrap());
+        let too_big = engine.submit(order_from_str("8 16 11 4 0 FOK").unwrap());
+        assert_eq!(too_big.status, OrderStatus::Cancelled);
+        assert!(too_big.trades.is_empty());
+        assert_eq!(engine.depth(10).1, levels(&[(10, 5)]));
+    }
+
+    #[test]
+    fn test_realized_pnl_fifo_long_and_short() {
+        let mut engine = MatchingEngine::new();
+        let mut submit = |line: &str, account: u64| {
+            let mut order = order_from_str(line).unwrap();
+            order.account = Some(account);
+            engine.submit(order);
+        };
+        submit("4 10 100 1 0", 2);
+        submit("8 10 100 2 0", 1);
+        submit("8 10 110 3 0", 2);
+        submit("4 10 110 4 0", 1);
+        // account 3 goes short 4@120 then 2@100 and buys back 5@90
+        submit("8 4 120 5 0", 4);
+        submit("4 4 120 6 0", 3);
+        submit("8 2 100 7 0", 4);
+        submit("4 2 100 8 0", 3);
+        submit("4 5 90 9 0", 4);
+        submit("8 5 90 10 0", 3);
+
+        assert_eq!(engine.realized_pnl(1).to_string(), "100");
+        assert_eq!(engine.realized_pnl(2).to_string(), "-100");
+        assert_eq!(engine.realized_pnl(3), Notional::ticks(4 * 30 + 10));
+        assert_eq!(engine.realized_pnl(9), Notional::ZERO);
+    }
+
+    #[test]
+    fn test_subscribers_each_receive_every_event() {
+        let mut engine = MatchingEngine::new();
+        engine.set_max_depth_per_side(Some(1));
+        let first = engine.subscribe();
+        let second = engine.subscribe();
+        engine.submit(order_from_str("4 5 12 1 0").unwrap());
+        engine.submit(order_from_str("8 2 10 2 0").unwrap());
+
+        let report = engine.submit(order_from_str("4 5 10 3 0").unwrap());
+        let busted = engine.bust_trade(report.trades[0].sequence).unwrap();
+
+        let events = engine.drain_events();
+        assert!(matches!(events[0], EngineEvent::Evicted(order) if order.price == 12));
+        assert_eq!(events[1], EngineEvent::Traded(report.trades[0]));
+        assert_eq!(events[2], EngineEvent::TradeBusted(busted));
+        assert_eq!(first.try_iter().collect::<Vec<_>>(), events);
+        assert_eq!(second.try_iter().collect::<Vec<_>>(), events);
+
+        drop(second);
+        engine.set_size_limits(Qty::units(2), Qty::MAX);
+        engine.submit(order_from_str("8 1 10 4 0").unwrap());
+        assert_eq!(first.try_iter().count(), 1);
+        assert_eq!(engine.events.subscribers.len(), 1);
+    }
+
+    #[test]
+    fn test_crossing_submit_sends_its_trades_to_subscribers() {
+        let mut engine = MatchingEngine::new();
+        engine.set_size_limits(Qty::units(2), Qty::MAX);
+        let events = engine.subscribe();
+        engine.submit(order_from_str("4 5 10 1 0").unwrap());
+        engine.submit(order_from_str("4 5 11 2 0").unwrap());
+        engine.submit(order_from_str("8 1 11 3 0").unwrap());
+
+        let report = engine.submit(order_from_str("8 7 11 4 0").unwrap());
+
+        let expected = vec![
+            EngineEvent::Rejected {
+                order_id: 0,
+                reason: SubmitError::BelowMinOrderQty,
+            },
+            EngineEvent::Traded(report.trades[0]),
+            EngineEvent::Traded(report.trades[1]),
+        ];
+        assert_eq!(events.try_iter().collect::<Vec<_>>(), expected);
+        assert_eq!(
+            report
+                .trades
+                .iter()
+                .map(|trade| (trade.sequence, trade.price, trade.amount))
+                .collect::<Vec<_>>(),
+            vec![(1, 10, Qty::units(5)), (2, 11, Qty::units(2))]
+        );
+    }
+
+    #[test]
+    fn test_bust_trade_flags_tape_and_emits_event() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("4 5 10 1 0").unwrap());
+        engine.submit(order_from_str("4 5 11 2 0").unwrap());
+        let report = engine.submit(order_from_str("8 10 11 3 0").unwrap());
+        let sequences: Vec<u64> = report.trades.iter().map(|trade| trade.sequence).collect();
+        assert_eq!(sequences, vec![1, 2]);
+        engine.drain_events();
+
+        let busted = engine.bust_trade(2).unwrap();
+
+        assert_eq!((busted.price, busted.busted), (11, true));
+        assert!(engine.trades()[1].busted);
+        assert!(!engine.trades()[0].busted);
+        assert_eq!(engine.traded_notional, notional(10, Qty::units(5)));
+        assert_eq!(
+            engine.drain_events(),
+            vec![EngineEvent::TradeBusted(busted)]
+        );
+        assert_eq!(engine.bust_trade(2), Err(BustError::AlreadyBusted));
+        assert_eq!(engine.bust_trade(3), Err(BustError::UnknownTrade));
+    }
+
+    #[test]
+    fn test_tape_capped_drops_oldest_trades() {
+        let mut engine = MatchingEngine::new();
+        engine.set_max_tape_len(Some(3));
+        engine.submit(order_from_str("4 10 10 1 0").unwrap());
+
+        for ts in 2..7 {
+            engine.submit(order_from_str(&format!("8 1 10 {} 0", ts)).unwrap());
+            assert!(engine.trades().len() <= 3);
+        }
+
+        let sequences: Vec<u64> = engine.trades().iter().map(|trade| trade.sequence).collect();
+        assert_eq!(sequences, vec![3, 4, 5]);
+        assert_eq!(engine.bust_trade(1), Err(BustError::UnknownTrade));
+    }
+
+    #[test]
+    fn test_candles_from_trade_tape() {
+        let clock = MockClock::new(5_000_000_000);
+        let mut engine = MatchingEngine::new();
+        engine.set_clock(Box::new(clock.clone()));
+        engine.submit(order_from_str("4 10 10 1 0").unwrap());
+        engine.submit(order_from_str("4 10 11 2 0").unwrap());
+        engine.submit(order_from_str("8 4 10 3 0").unwrap());
+        clock.advance(Duration::from_millis(300));
+        engine.submit(order_from_str("8 8 11 4 0").unwrap());
+
+        let candles = engine.candles(Duration::from_secs(1));
+
+        assert_eq!(
+            candles,
+            vec![Candle {
+                open: 10,
+                high: 11,
+                low: 10,
+                close: 11,
+                volume: Qty::units(12),
+                start_ts: 5_000_000_000,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_sub_unit_candle_interval_rounds_up_to_one_unit() {
+        let clock = MockClock::new(5_000_000_000);
+        let mut engine = MatchingEngine::new();
+        engine.set_clock(Box::new(clock.clone()));
+        engine.set_time_unit(TimeUnit::Millis);
+        engine.submit(order_from_str("4 10 10 1 0").unwrap());
+        engine.submit(order_from_str("8 4 10 2 0").unwrap());
+        clock.advance(Duration::from_millis(1));
+        engine.submit(order_from_str("8 2 10 3 0").unwrap());
+
+        let starts: Vec<u128> = engine
+            .candles(Duration::from_micros(500))
+            .iter()
+            .map(|candle| candle.start_ts)
+            .collect();
+
+        assert_eq!(starts, vec![5_000, 5_001]);
+        assert_eq!(engine.candles(Duration::ZERO), vec![]);
+    }
+
+    #[test]
+    fn test_quantity_above_i32_max_is_not_truncated() {
+        let mut engine = MatchingEngine::new();
+        let amount = Qty::units(i64::from(i32::MAX) + 10);
+        engine.submit(Order::limit(Side::Ask, amount, 10, 1));
+
+        let report = engine.submit(Order::limit(Side::Bid, amount - Qty::units(1), 10, 2));
+
+        assert_has_one_trade(report.trades, amount - Qty::units(1), 10);
+        assert_eq!(engine.depth(1).1, levels(&[(10, 1)]));
+    }
+
+    #[test]
+    fn test_submit_traced_records_two_level_sweep() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("4 5 10 1 0").unwrap());
+        engine.submit(order_from_str("4 5 11 2 0").unwrap());
+        engine.submit(order_from_str("4 5 12 3 0").unwrap());
+
+        let (report, steps) = engine.submit_traced(order_from_str("8 12 11 4 0").unwrap());
+
+        assert_eq!(report.status, OrderStatus::PartiallyFilled);
+        assert_eq!(
+            steps,
+            vec![
+                MatchStep::Peeked {
+                    order_id: 1,
+                    crossed: true
+                },
+                MatchStep::Matched {
+                    order_id: 1,
+                    amount: Qty::units(5),
+                    price: 10
+                },
+                MatchStep::Peeked {
+                    order_id: 2,
+                    crossed: true
+                },
+                MatchStep::Matched {
+                    order_id: 2,
+                    amount: Qty::units(5),
+                    price: 11
+                },
+                MatchStep::Peeked {
+                    order_id: 3,
+                    crossed: false
+                },
+                MatchStep::Rested {
+                    remainder: Qty::units(2)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trade_timestamps_never_decrease() {
+        let clock = MockClock::new(5_000);
+        let mut engine = MatchingEngine::new();
+        engine.set_clock(Box::new(clock.clone()));
+        engine.submit(order_from_str("4 10 10 1 0").unwrap());
+
+        let mut timestamps = Vec::new();
+        for now in [5_000, 4_000, 6_000, 3_000].iter() {
+            clock.set(*now);
+            let report = engine.submit(order_from_str("8 1 10 2 0").unwrap());
+            timestamps.push(report.trades[0].timestamp);
+        }
+
+        assert_eq!(timestamps, vec![5_000, 5_000, 6_000, 6_000]);
+    }
+
+    #[test]
+    fn test_timestamps_in_configured_unit() {
+        let stamp_in = |time_unit: TimeUnit| {
+            let mut engine = MatchingEngine::new();
+            engine.set_clock(Box::new(MockClock::new(5_123_456_789)));
+            engine.set_time_unit(time_unit);
+            engine.submit(order_from_str("4 1 10 1 0").unwrap());
+            engine.submit(order_from_str("8 1 10 2 0").unwrap()).trades[0].timestamp
+        };
+
+        assert_eq!(stamp_in(TimeUnit::Nanos), 5_123_456_789);
+        assert_eq!(stamp_in(TimeUnit::Micros), 5_123_456);
+        assert_eq!(stamp_in(TimeUnit::Millis), 5_123);
+    }
+
+    #[test]
+    fn test_order_inactive_until_not_before() {
+        let clock = MockClock::new(1_000);
+        let mut engine = MatchingEngine::new();
+        engine.set_clock(Box::new(clock.clone()));
+        engine.submit(order_from_str("4 5 10 1 0").unwrap());
+        let mut order = order_from_str("8 5 10 2 0").unwrap();
+        order.not_before = Some(2_000);
+
+        let report = engine.submit(order);
+
+        assert_eq!(report.status, OrderStatus::Pending);
+        assert_eq!(engine.depth(10), (vec![], levels(&[(10, 5)])));
+        clock.set(1_500);
+        assert!(engine.tick(1_500).is_empty());
+        assert_eq!(engine.depth(10), (vec![], levels(&[(10, 5)])));
+
+        clock.set(2_000);
+        let trades = engine.tick(2_000);
+
+        assert_eq!(trades.len(), 1);
+        assert_eq!(engine.depth(10), (vec![], vec![]));
+    }
+
+    #[test]
+    fn test_cancelled_pending_order_never_activates() {
+        let clock = MockClock::new(1_000);
+        let mut engine = MatchingEngine::new();
+        engine.set_clock(Box::new(clock.clone()));
+        engine.submit(order_from_str("4 5 10 1 0").unwrap());
+        let mut order = order_from_str("8 5 10 2 0").unwrap();
+        order.not_before = Some(2_000);
+        engine.submit(order);
+
+        let pending: Vec<u64> = engine.pending_orders().iter().map(|o| o.id).collect();
+        assert_eq!(pending, vec![2]);
+        assert!(engine.cancel_pending(1).is_none());
+        assert_eq!(engine.cancel_pending(2).unwrap().id, 2);
+
+        clock.set(2_000);
+        assert!(engine.tick(2_000).is_empty());
+        assert!(engine.pending_orders().is_empty());
+        assert_eq!(engine.depth(10), (vec![], levels(&[(10, 5)])));
+    }
+
+    #[test]
+    fn test_buy_market_if_touched_activates_on_trade_at_trigger() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("8 1 9 1 0").unwrap());
+        engine.submit(order_from_str("8 4 8 2 0").unwrap());
+        engine.submit(order_from_str("4 5 10 3 0").unwrap());
+        let report = engine.submit(Order::market_if_touched(Side::Bid, Qty::units(2), 8, 4));
+        assert_eq!(report.status, OrderStatus::Pending);
+
+        engine.submit(order_from_str("4 1 9 5 0").unwrap());
+        assert_eq!(engine.pending_orders().len(), 1);
+
+        engine.submit(order_from_str("4 1 8 6 0").unwrap());
+
+        assert!(engine.pending_orders().is_empty());
+        let last = engine.trades().last().unwrap();
+        assert_eq!((last.price, last.amount), (10, Qty::units(2)));
+        assert_eq!(engine.depth(10).1, levels(&[(10, 3)]));
+    }
+
+    #[test]
+    fn test_compact_after_cancels_keeps_priority() {
+        let mut engine = MatchingEngine::<HeapBook>::with_books();
+        for price in 1..=200 {
+            engine.submit(order_from_str(&format!("8 1 {} 1 0", price)).unwrap());
+        }
+        let full_capacity = engine.bids.capacity();
+        for id in 1..=190 {
+            engine.cancel(id).unwrap();
+        }
+        assert_eq!(engine.bids.capacity(), full_capacity);
+        engine.compact();
+
+        assert!(engine.bids.capacity() < full_capacity);
+        assert_eq!(engine.bids.len(), 10);
+        let levels: Vec<PriceLevel> = (191..=200)
+            .rev()
+            .map(|price| (price, Qty::units(1)))
+            .collect();
+        assert_eq!(engine.depth(usize::MAX).0, levels);
+    }
+
+    #[test]
+    fn test_cancel_before_min_rest_is_too_soon() {
+        let clock = MockClock::new(1_000_000_000);
+        let mut engine = MatchingEngine::new();
+        engine.set_clock(Box::new(clock.clone()));
+        engine.set_min_rest(Some(Duration::from_millis(5)));
+        engine.submit(order_from_str("8 5 10 1 0").unwrap());
+
+        assert_eq!(engine.cancel(1), Err(SubmitError::TooSoon));
+        clock.advance(Duration::from_nanos(4_999_999));
+        assert_eq!(engine.cancel(1), Err(SubmitError::TooSoon));
+        clock.advance(Duration::from_nanos(1));
+        assert_eq!(engine.cancel(1).map(|order| order.id), Ok(1));
+        assert_eq!(SubmitError::TooSoon.code(), "too_soon");
+    }
+
+    #[test]
+    fn test_cancel_returns_filled_and_remaining() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("8 10 10 1 0").unwrap());
+        engine.submit(order_from_str("4 3 10 2 0").unwrap());
+        engine.submit(order_from_str("4 4 10 3 0").unwrap());
+
+        let cancelled = engine.cancel(1).unwrap();
+
+        assert_eq!(cancelled.filled, Qty::units(7));
+        assert_eq!(cancelled.amount, Qty::units(3));
+        assert_eq!(engine.cancel(1), Err(SubmitError::UnknownOrderId));
+    }
+
+    #[test]
+    fn test_cancel_one_of_two_leaves_the_other_matching() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("4 5 10 1 0").unwrap());
+        engine.submit(order_from_str("4 5 11 2 0").unwrap());
+
+        assert_eq!(engine.cancel(1).map(|order| order.price), Ok(10));
+        assert_eq!(engine.depth(10).1, levels(&[(11, 5)]));
+
+        let report = engine.submit(order_from_str("8 5 11 3 0").unwrap());
+        assert_has_one_trade(report.trades, Qty::units(5), 11);
+        assert_eq!(engine.total_order_count(), 0);
+        assert_eq!(engine.cancel(2), Err(SubmitError::UnknownOrderId));
+        assert_eq!(
+            engine.amend(2, Qty::units(1), 11).unwrap_err(),
+            SubmitError::UnknownOrderId
+        );
+    }
+
+    #[test]
+    fn test_cancel_at_queue_position() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("8 1 10 1 0").unwrap());
+        engine.submit(order_from_str("8 2 10 2 0").unwrap());
+        engine.submit(order_from_str("8 3 10 3 0").unwrap());
+        engine.submit(order_from_str("8 4 11 4 0").unwrap());
+
+        let cancelled = engine.cancel_at(Side::Bid, 10, 1).unwrap();
+
+        assert_eq!(cancelled.id, 2);
+        assert_eq!(engine.queue_position(1), Some((0, Qty::ZERO)));
+        assert_eq!(engine.queue_position(3), Some((1, Qty::units(1))));
+        assert!(engine.cancel_at(Side::Bid, 10, 2).is_err());
+        assert!(engine.cancel_at(Side::Ask, 10, 0).is_err());
+    }
+
+    #[test]
+    fn test_amend_preserving_ids() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("8 5 10 1 0").unwrap());
+        engine.submit(order_from_str("8 5 10 2 0").unwrap());
+
+        let amended = engine.amend(1, Qty::units(3), 10).unwrap();
+        assert_eq!(amended.status, OrderStatus::Resting);
+        assert_eq!(engine.queue_position(1), Some((0, Qty::ZERO)));
+        engine.amend(1, Qty::units(4), 10).unwrap();
+        assert_eq!(engine.queue_position(1), Some((1, Qty::units(5))));
+        engine.amend(2, Qty::units(5), 9).unwrap();
+        assert_eq!(engine.queue_position(2), Some((0, Qty::ZERO)));
+        assert_eq!(engine.depth(10).0, levels(&[(10, 4), (9, 5)]));
+        assert_eq!(
+            engine.amend(7, Qty::units(5), 9).unwrap_err(),
+            SubmitError::UnknownOrderId
+        );
+    }
+
+    #[test]
+    fn test_amend_assigning_new_id_on_reprice() {
+        let mut engine = MatchingEngine::new();
+        engine.set_amend_id_policy(AmendIdPolicy::NewId);
+        engine.submit(order_from_str("8 5 10 1 0").unwrap());
+        engine.submit(order_from_str("8 5 10 2 0").unwrap());
+
+        assert_eq!(engine.amend(1, Qty::units(3), 10).unwrap().order_id, 1);
+        assert_eq!(engine.queue_position(1), Some((0, Qty::ZERO)));
+        let new_id = engine.amend(1, Qty::units(3), 11).unwrap().order_id;
+
+        assert_ne!(new_id, 1);
+        assert_eq!(engine.queue_position(1), None);
+        assert_eq!(engine.queue_position(new_id), Some((0, Qty::ZERO)));
+        assert_eq!(engine.depth(10).0, levels(&[(11, 3), (10, 5)]));
+    }
+
+    #[test]
+    fn test_non_positive_tick_size_is_refused() {
+        let mut engine = MatchingEngine::new();
+        engine.set_tick_size(Some(5)).unwrap();
+
+        assert_eq!(engine.set_tick_size(Some(0)), Err(InvalidTickSize(0)));
+        assert_eq!(engine.set_tick_size(Some(-5)), Err(InvalidTickSize(-5)));
+        assert_eq!(
+            engine
+                .submit(order_from_str("8 5 103 1 0").unwrap())
+                .error(),
+            Some(SubmitError::OffTick)
+        );
+        assert_eq!(engine.set_tick_size(None), Ok(()));
+        assert_eq!(
+            engine.submit(order_from_str("8 5 103 2 0").unwrap()).status,
+            OrderStatus::Resting
+        );
+    }
+
+    #[test]
+    fn test_off_tick_amend_rejected() {
+        let mut engine = MatchingEngine::new();
+        engine.set_tick_size(Some(5)).unwrap();
+        engine.submit(order_from_str("8 5 100 1 0").unwrap());
+
+        assert_eq!(
+            engine.amend(1, Qty::units(5), 103).unwrap_err(),
+            SubmitError::OffTick
+        );
+        assert_eq!(engine.depth(10).0, levels(&[(100, 5)]));
+        assert_eq!(
+            engine.drain_events(),
+            vec![EngineEvent::Rejected {
+                order_id: 1,
+                reason: SubmitError::OffTick,
+            }]
+        );
+        engine.amend(1, Qty::units(5), 105).unwrap();
+        assert_eq!(engine.depth(10).0, levels(&[(105, 5)]));
+    }
+
+    #[test]
+    fn test_duplicate_live_order_id_rejected() {
+        let mut engine = MatchingEngine::new();
+        let mut first = order_from_str("8 5 10 1 0").unwrap();
+        first.id = 42;
+        let mut second = order_from_str("8 5 9 2 0").unwrap();
+        second.id = 42;
+
+        assert_eq!(engine.submit(first).status, OrderStatus::Resting);
+        let report = engine.submit(second);
+
+        assert_eq!(report.error(), Some(SubmitError::DuplicateOrderId));
+        assert_eq!(engine.depth(10).0, levels(&[(10, 5)]));
+        assert_eq!(
+            engine.submit(order_from_str("8 1 8 3 0").unwrap()).status,
+            OrderStatus::Resting
+        );
+        assert_eq!(engine.queue_position(43), Some((0, Qty::ZERO)));
+    }
+
+    #[test]
+    fn test_reuse_of_filled_order_id() {
+        let mut reusing = MatchingEngine::new();
+        let mut rejecting = MatchingEngine::new();
+        rejecting.set_reject_reused_ids(true);
+        for engine in [&mut reusing, &mut rejecting].iter_mut() {
+            let mut order = order_from_str("8 5 10 1 0").unwrap();
+            order.id = 42;
+            engine.submit(order);
+            engine.submit(order_from_str("4 5 10 2 0").unwrap());
+        }
+        let mut reused = order_from_str("8 5 9 3 0").unwrap();
+        reused.id = 42;
+
+        assert_eq!(reusing.submit(reused).status, OrderStatus::Resting);
+        assert_eq!(
+            rejecting.submit(reused).error(),
+            Some(SubmitError::DuplicateOrderId)
+        );
+    }
+
+    #[test]
+    fn test_reuse_of_id_assigned_by_amend() {
+        let mut engine = MatchingEngine::new();
+        engine.set_reject_reused_ids(true);
+        engine.set_amend_id_policy(AmendIdPolicy::NewId);
+        engine.submit(order_from_str("8 5 10 1 0").unwrap());
+        let new_id = engine.amend(1, Qty::units(5), 11).unwrap().order_id;
+        engine.submit(order_from_str("4 5 11 3 0").unwrap());
+        let mut reused = order_from_str("8 5 9 4 0").unwrap();
+        reused.id = new_id;
+
+        assert_eq!(
+            engine.submit(reused).error(),
+            Some(SubmitError::DuplicateOrderId)
+        );
+    }
+
+    #[test]
+    fn test_submit_reports_the_order_id() {
+        let mut engine = MatchingEngine::new();
+        engine.set_size_limits(Qty::units(2), Qty::MAX);
+        let mut client = order_from_str("8 5 10 1 0").unwrap();
+        client.id = 42;
+
+        assert_eq!(engine.submit(client).order_id, 42);
+        let assigned = engine.submit(order_from_str("8 5 9 2 0").unwrap());
+        assert_eq!(assigned.order_id, 43);
+        assert_eq!(
+            engine.queue_position(assigned.order_id),
+            Some((0, Qty::ZERO))
+        );
+        let too_small = engine.submit(order_from_str("8 1 9 3 0").unwrap());
+        assert_eq!(too_small.order_id, 0);
+    }
+
+    #[test]
+    fn test_client_id_at_u64_max() {
+        let mut engine = MatchingEngine::new();
+        let mut order = order_from_str("8 5 10 1 0").unwrap();
+        order.id = u64::MAX;
+
+        assert_eq!(engine.submit(order).status, OrderStatus::Resting);
+        assert_eq!(engine.queue_position(u64::MAX), Some((0, Qty::ZERO)));
+
+        let report = engine.submit(order_from_str("8 5 9 2 0").unwrap());
+        assert_eq!(
+            report.status,
+            OrderStatus::Rejected(SubmitError::IdsExhausted)
+        );
+        assert_eq!(engine.rejected_count, 1);
+        assert_eq!(engine.bids.len(), 1);
+    }
+
+    #[test]
+    fn test_order_below_min_qty_rejected() {
+        let mut engine = MatchingEngine::<HeapBook>::with_books();
+        engine.set_size_limits(Qty::units(5), Qty::units(100));
+
+        let report = engine.submit(order_from_str("8 4 10 1 0").unwrap());
+
+        assert_eq!(
+            report.status,
+            OrderStatus::Rejected(SubmitError::BelowMinOrderQty)
+        );
+        assert_eq!(engine.bids.into_sorted_vec(), []);
+    }
+
+    #[test]
+    fn test_order_above_max_qty_rejected() {
+        let mut engine = MatchingEngine::<HeapBook>::with_books();
+        engine.set_size_limits(Qty::units(5), Qty::units(100));
+
+        let report = engine.submit(order_from_str("8 101 10 1 0").unwrap());
+
+        assert_eq!(
+            report.status,
+            OrderStatus::Rejected(SubmitError::AboveMaxOrderQty)
+        );
+        assert_eq!(engine.bids.into_sorted_vec(), []);
+    }
+
+    #[test]
+    fn test_risk_rejections_are_recorded_as_events() {
+        let mut engine = MatchingEngine::new();
+        engine.set_size_limits(Qty::units(5), Qty::units(100));
+        engine.set_price_band(Some((90, 110)));
+        let mut outside_band = order_from_str("8 5 111 1 0").unwrap();
+        outside_band.id = 7;
+
+        let band = engine.submit(outside_band);
+        let size = engine.submit(order_from_str("8 4 100 2 0").unwrap());
+        engine.submit(order_from_str("8 5 100 3 0").unwrap());
+
+        assert_eq!(band.error(), Some(SubmitError::OutsidePriceBand));
+        assert_eq!(size.error(), Some(SubmitError::BelowMinOrderQty));
+        assert_eq!(
+            engine.drain_events(),
+            vec![
+                EngineEvent::Rejected {
+                    order_id: 7,
+                    reason: SubmitError::OutsidePriceBand,
+                },
+                EngineEvent::Rejected {
+                    order_id: 0,
+                    reason: SubmitError::BelowMinOrderQty,
+                },
+            ]
+        );
+        assert_eq!(SubmitError::OutsidePriceBand.code(), "outside_price_band");
+        assert_eq!(
+            engine.amend(1, Qty::units(5), 120).unwrap_err(),
+            SubmitError::OutsidePriceBand
+        );
+        assert_eq!(
+            engine.drain_events(),
+            vec![EngineEvent::Rejected {
+                order_id: 1,
+                reason: SubmitError::OutsidePriceBand,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_order_within_size_limits_accepted() {
+        let mut engine = MatchingEngine::new();
+        engine.set_size_limits(Qty::units(5), Qty::units(100));
+
+        let report = engine.submit(order_from_str("8 5 10 1 0").unwrap());
+
+        assert_eq!(report.status, OrderStatus::Resting);
+        assert_eq!(engine.bids.len(), 1);
+    }
+
+    #[test]
+    fn test_two_sided_quote_rests_both_orders() {
+        let mut engine = MatchingEngine::<HeapBook>::with_books();
+
+        let (bid_report, ask_report) = engine.quote(
+            order_from_str("8 10 9 1 0").unwrap(),
+            order_from_str("4 10 11 1 0").unwrap(),
+        );
+
+        assert_eq!(bid_report.status, OrderStatus::Resting);
+        assert_eq!(ask_report.status, OrderStatus::Resting);
+        assert_eq!(engine.bids.peek().unwrap().price, 9);
+        assert_eq!(engine.asks.peek().unwrap().price, 11);
+    }
+
+    #[test]
+    fn test_place_grid_rests_post_only_levels() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("4 5 12 1 0").unwrap());
+
+        let reports = engine.place_grid(Side::Bid, &levels(&[(11, 1), (10, 2), (9, 3), (12, 4)]));
+
+        let statuses: Vec<OrderStatus> = reports.iter().map(|report| report.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                OrderStatus::Resting,
+                OrderStatus::Resting,
+                OrderStatus::Resting,
+                OrderStatus::Rejected(SubmitError::WouldCross),
+            ]
+        );
+        assert_eq!(engine.depth(10).0, levels(&[(11, 1), (10, 2), (9, 3)]));
+        assert_eq!(engine.depth(10).1, levels(&[(12, 5)]));
+        assert_eq!(SubmitError::WouldCross.code(), "would_cross");
+    }
+
+    #[test]
+    fn test_mass_quote_replaces_account_quotes() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("8 7 9 1 0").unwrap());
+        let quote = |line: &str| order_from_str(line).unwrap();
+        engine.mass_quote(
+            7,
+            vec![
+                quote("8 1 10 2 0"),
+                quote("8 2 9 2 0"),
+                quote("4 1 11 2 0"),
+                quote("4 2 12 2 0"),
+            ],
+        );
+
+        let reports = engine.mass_quote(
+            7,
+            vec![
+                quote("8 3 10 3 0"),
+                quote("8 4 8 3 0"),
+                quote("4 3 12 3 0"),
+                quote("4 4 13 3 0"),
+            ],
+        );
+
+        assert!(reports
+            .iter()
+            .all(|report| report.status == OrderStatus::Resting));
+        assert_eq!(engine.depth(10).0, levels(&[(10, 3), (9, 7), (8, 4)]));
+        assert_eq!(engine.depth(10).1, levels(&[(12, 3), (13, 4)]));
+        assert!(engine.asks().all(|order| order.account == Some(7)));
+    }
+
+    #[test]
+    fn test_mass_quote_rejects_every_quote_when_one_would_cross() {
+        let mut engine = MatchingEngine::new();
+        let quote = |line: &str| order_from_str(line).unwrap();
+        engine.mass_quote(7, vec![quote("8 1 10 1 0"), quote("4 1 12 1 0")]);
+        engine.submit(quote("4 5 11 2 0"));
+
+        let reports = engine.mass_quote(7, vec![quote("8 1 9 3 0"), quote("8 1 11 3 0")]);
+
+        assert!(reports
+            .iter()
+            .all(|report| report.error() == Some(SubmitError::WouldCross)));
+        assert_eq!(engine.depth(10).0, levels(&[(10, 1)]));
+        assert_eq!(engine.depth(10).1, levels(&[(11, 5), (12, 1)]));
+    }
+
+    #[test]
+    fn test_mass_quote_refreshes_a_capped_level() {
+        let mut engine = MatchingEngine::new();
+        engine.set_max_orders_per_level(Some(1));
+        let quote = |line: &str| order_from_str(line).unwrap();
+        engine.mass_quote(7, vec![quote("8 1 10 1 0")]);
+
+        let reports = engine.mass_quote(7, vec![quote("8 2 10 2 0")]);
+
+        assert_eq!(reports[0].status, OrderStatus::Resting);
+        assert_eq!(engine.depth(10).0, levels(&[(10, 2)]));
+    }
+
+    #[test]
+    fn test_mass_quote_counts_its_own_quotes_against_the_level_cap() {
+        let mut engine = MatchingEngine::new();
+        engine.set_max_orders_per_level(Some(1));
+        let quote = |line: &str| order_from_str(line).unwrap();
+        engine.mass_quote(7, vec![quote("8 1 10 1 0")]);
+
+        let reports = engine.mass_quote(7, vec![quote("8 2 10 2 0"), quote("8 3 10 2 0")]);
+
+        assert!(reports
+            .iter()
+            .all(|report| report.error() == Some(SubmitError::PriceLevelFull)));
+        assert_eq!(engine.depth(10).0, levels(&[(10, 1)]));
+    }
+
+    #[test]
+    fn test_mass_quote_market_quote_needs_liquidity_beyond_its_own_quotes() {
+        let mut engine = MatchingEngine::new();
+        let quote = |line: &str| order_from_str(line).unwrap();
+        engine.mass_quote(7, vec![quote("4 1 12 1 0")]);
+
+        let reports = engine.mass_quote(7, vec![quote("8 1 0 2 1")]);
+
+        assert_eq!(reports[0].error(), Some(SubmitError::NoLiquidity));
+        assert_eq!(engine.depth(10).1, levels(&[(12, 1)]));
+    }
+
+    #[test]
+    fn test_mass_quote_rejects_every_quote_when_the_side_fills_up() {
+        let mut engine = MatchingEngine::new();
+        engine.set_max_depth_per_side(Some(2));
+        let quote = |line: &str| order_from_str(line).unwrap();
+        engine.submit(quote("8 5 11 1 0"));
+        engine.mass_quote(7, vec![quote("8 1 8 2 0")]);
+
+        let reports = engine.mass_quote(7, vec![quote("8 1 10 3 0"), quote("8 1 9 3 0")]);
+
+        assert!(reports
+            .iter()
+            .all(|report| report.error() == Some(SubmitError::BookFull)));
+        assert_eq!(engine.depth(10).0, levels(&[(11, 5), (8, 1)]));
+        assert!(engine.drain_events().is_empty());
+    }
+
+    #[test]
+    fn test_mass_quote_cancels_queued_quotes() {
+        let mut engine = MatchingEngine::new();
+        engine.set_auction_window(Duration::from_secs(1));
+        let quote = |line: &str| order_from_str(line).unwrap();
+        let queued = engine.mass_quote(7, vec![quote("8 1 10 1 0")]);
+        assert_eq!(queued[0].status, OrderStatus::Queued);
+
+        engine.mass_quote(7, vec![quote("8 2 9 2 0")]);
+
+        assert_eq!(engine.cancel(1), Err(SubmitError::UnknownOrderId));
+        assert_eq!(
+            engine.cancel(2).map(|order| order.amount),
+            Ok(Qty::units(2))
+        );
+    }
+
+    #[test]
+    fn test_book_or_cancel_slides_within_tolerance() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("4 5 100 1 0").unwrap());
+        let book_or_cancel = |line: &str, tolerance: PxTicks| {
+            let mut order = order_from_str(line).unwrap();
+            order.time_in_force = TimeInForce::BookOrCancel { tolerance };
+            order
+        };
+
+        let report = engine.submit(book_or_cancel("8 2 101 2 0", 1));
+        let rejected = engine.submit(book_or_cancel("8 2 102 3 0", 1));
+        let resting = engine.submit(book_or_cancel("8 2 95 4 0", 0));
+
+        assert_eq!(report.status, OrderStatus::Resting);
+        assert_eq!(rejected.error(), Some(SubmitError::WouldCross));
+        assert_eq!(resting.status, OrderStatus::Resting);
+        assert!(engine.trades().is_empty());
+        assert_eq!(engine.depth(10).0, levels(&[(99, 2), (95, 2)]));
+    }
+
+    #[test]
+    fn test_book_or_cancel_amend_slides_within_tolerance() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("4 5 100 1 0").unwrap());
+        let mut order = order_from_str("8 2 95 2 0").unwrap();
+        order.time_in_force = TimeInForce::BookOrCancel { tolerance: 1 };
+        engine.submit(order);
+
+        let repriced = engine.amend(2, Qty::units(2), 101).unwrap();
+        let too_far = engine.amend(2, Qty::units(3), 102);
+
+        assert_eq!(repriced.status, OrderStatus::Resting);
+        assert_eq!(too_far.unwrap_err(), SubmitError::WouldCross);
+        assert!(engine.trades().is_empty());
+        assert_eq!(engine.depth(10).0, levels(&[(99, 2)]));
+    }
+
+    #[test]
+    fn test_book_or_cancel_counts_tolerance_in_ticks_and_checks_the_new_price() {
+        let mut engine = MatchingEngine::new();
+        engine.set_tick_size(Some(5)).unwrap();
+        engine.set_price_band(Some((50, 100)));
+        engine.set_max_orders_per_level(Some(1));
+        engine.submit(order_from_str("4 5 100 1 0").unwrap());
+        let book_or_cancel = |line: &str, tolerance: PxTicks| {
+            let mut order = order_from_str(line).unwrap();
+            order.time_in_force = TimeInForce::BookOrCancel { tolerance };
+            order
+        };
+
+        let repriced = engine.submit(book_or_cancel("8 2 110 2 0", 2));
+        let too_far = engine.submit(book_or_cancel("8 2 115 3 0", 2));
+        let level_full = engine.submit(book_or_cancel("8 2 105 4 0", 1));
+
+        assert_eq!(repriced.status, OrderStatus::Resting);
+        assert_eq!(too_far.error(), Some(SubmitError::WouldCross));
+        assert_eq!(level_full.error(), Some(SubmitError::PriceLevelFull));
+        assert_eq!(engine.depth(10).0, levels(&[(95, 2)]));
+    }
+
+    #[test]
+    fn test_self_crossing_quote_rejects_both_orders() {
+        let mut engine = MatchingEngine::<HeapBook>::with_books();
+
+        let (bid_report, ask_report) = engine.quote(
+            order_from_str("8 10 11 1 0").unwrap(),
+            order_from_str("4 10 10 1 0").unwrap(),
+        );
+
+        let rejected = OrderStatus::Rejected(SubmitError::SelfCrossingQuote);
+        assert_eq!(bid_report.status, rejected);
+        assert_eq!(ask_report.status, rejected);
+        assert_eq!(engine.asks.into_sorted_vec(), []);
+        assert_eq!(engine.bids.into_sorted_vec(), []);
+    }
+
+    #[test]
+    fn test_quote_leg_failing_book_or_cancel_rejects_both() {
+        let mut engine = MatchingEngine::new();
+        engine.submit(order_from_str("8 5 10 1 0").unwrap());
+        let mut ask = order_from_str("4 5 9 2 0").unwrap();
+        ask.time_in_force = TimeInForce::BookOrCancel { tolerance: 0 };
+
+        let (bid_report, ask_report) = engine.quote(order_from_str("8 5 8 2 0").unwrap(), ask);
+
+        assert_eq!(bid_report.error(), Some(SubmitError::WouldCross));
+        assert_eq!(ask_report.error(), Some(SubmitError::WouldCross));
+        assert_eq!(engine.depth(10), (levels(&[(10, 5)]), vec![]));
+    }
+
+    #[test]
+    fn test_quote_leg_that_cannot_rest_on_a_full_side_rejects_both() {
+        let mut engine = MatchingEngine::new();
+        engine.set_max_depth_per_side(Some(1));
+        engine.submit(order_from_str("4 5 12 1 0").unwrap());
+
+        let (bid_report, ask_report) = engine.quote(
+            order_from_str("8 5 10 2 0").unwrap(),
+            order_from_str("4 5 13 2 0").unwrap(),
+        );
+
+        assert_eq!(bid_report.error(), Some(SubmitError::BookFull));
+        assert_eq!(ask_report.error(), Some(SubmitError::BookFull));
+        assert_eq!(engine.depth(10), (vec![], levels(&[(12, 5)])));
+    }
+
+    #[test]
+    fn test_every_rejection_has_a_reason_code() {
+        let mut engine = MatchingEngine::new();
+        engine.set_size_limits(Qty::units(5), Qty::units(100));
+
+        let too_small = engine.submit(order_from_str("8 4 10 1 0").unwrap());
+        let too_large = engine.submit(order_from_str("8 101 10 2 0").unwrap());
+        let (swapped, _) = engine.quote(
+            order_from_str("4 10 11 3 0").unwrap(),
+            order_from_str("8 10 9 4 0").unwrap(),
+        );
+        let (crossed, _) = engine.quote(
+            order_from_str("8 10 11 5 0").unwrap(),
+            order_from_str("4 10 10 6 0").unwrap(),
+        );
+
+        let errors: Vec<SubmitError> = [too_small, too_large, swapped, crossed]
+            .iter()
+            .map(|report| report.error().unwrap())
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                SubmitError::BelowMinOrderQty,
+                SubmitError::AboveMaxOrderQty,
+                SubmitError::InvalidQuoteSides,
+                SubmitError::SelfCrossingQuote,
+            ]
+        );
+        let codes: Vec<&str> = errors.iter().map(SubmitError::code).collect();
+        assert_eq!(
+            codes,
+            vec![
+                "below_min_order_qty",
+                "above_max_order_qty",
+                "invalid_quote_sides",
+                "self_crossing_quote",
+            ]
+        );
+        assert_eq!(
+            SubmitError::SelfCrossingQuote.to_string(),
+            "order rejected: self_crossing_quote"
+        );
+    }
+
+    #[test]
+    fn test_snapshot_json_is_deterministic() {
+        let lines = [
+            "8 5 9 1 0",
+            "8 3 10 2 0",
+            "8 2 9 3 0",
+            "4 4 12 4 0",
+            "4 6 11 5 0",
+        ];
+        let mut engine = MatchingEngine::new();
+        for line in lines.iter() {
+            engine.submit(order_from_str(line).unwrap());
+        }
+        let mut reversed_engine = MatchingEngine::new();
+        for line in lines.iter().rev() {
+            reversed_engine.submit(order_from_str(line).unwrap());
+        }
+
+        let expected = concat!(
+            "{\"bids\":[",
+            "{\"price\":10,\"amount\":\"3\",\"timestamp\":2},",
+            "{\"price\":9,\"amount\":\"5\",\"timestamp\":1},",
+            "{\"price\":9,\"amount\":\"2\",\"timestamp\":3}",
+            "],\"asks\":[",
+            "{\"price\":11,\"amount\":\"6\",\"timestamp\":5},",
+            "{\"price\":12,\"amount\":\"4\",\"timestamp\":4}",
+            "],\"counters\":{\"submitted\":5,\"rejected\":0,\"trades\":0,\"notional\":\"0\"}}"
+        );
+        assert_eq!(engine.snapshot_json(), expected);
+        assert_eq!(reversed_engine.snapshot_json(), expected);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_trades_to_json() {
+        let mut engine = MatchingEngine::new();
+        engine.set_clock(Box::new(MockClock::new(1_000)));
+        engine.submit(order_from_str("4 3 10 1 0").unwrap());
+        engine.submit(order_from_str("4 2 11 2 0").unwrap());
+        let report = engine.submit(order_from_str("8 4.5 11 3 0").unwrap());
+
+        assert_eq!(
+            MatchingEngine::trades_to_json(&report.trades),
+            concat!(
+                "[{\"sequence\":1,\"executing_order_id\":3,\"matched_order_id\":1,\"timestamp\":1000,",
+                "\"amount\":\"3\",\"price\":10,\"maker_edge_bps\":null,\"external\":false,",
+                "\"buyer_account\":null,\"seller_account\":null,\"busted\":false},",
+                "{\"sequence\":2,\"executing_order_id\":3,\"matched_order_id\":2,\"timestamp\":1000,",
+                "\"amount\":\"1.5\",\"price\":11,\"maker_edge_bps\":null,\"external\":false,",
+                "\"buyer_account\":null,\"seller_account\":null,\"busted\":false}]"
+            )
+        );
+        assert_eq!(MatchingEngine::trades_to_json(&VecDeque::new()), "[]");
+    }
+
+    fn levels(levels: &[(PxTicks, i64)]) -> Vec<PriceLevel> {
+        levels
+            .iter()
+            .map(|&(price, units)| (price, Qty::units(units)))
+            .collect()
+    }
+
+    fn assert_has_one_trade(trades: VecDeque<Trade>, amount: Qty, price: PxTicks) {
+        assert_eq!(trades.len(), 1);
+        let only_trade = trades.front().unwrap();
+        assert_eq!(only_trade.amount, amount);
+        assert_eq!(only_trade.price, price);
+    }
+}
@@ -0,0 +1,12 @@
+#[cfg(feature = "async")]
+pub mod async_engine;
+pub mod book;
+pub mod candles;
+pub mod clock;
+pub mod engine;
+pub mod orders;
+pub mod snapshot;
+pub mod venue;
+
+pub use engine::MatchingEngine;
+pub use orders::{Order, Side, Strategy, Trade};
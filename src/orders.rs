@@ -1,64 +1,490 @@
 use std::cmp::Ordering;
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Sub, SubAssign};
 use std::str::FromStr;
 
+pub type PxTicks = i64;
+
+/// Quantity in base units of `1 / Qty::SCALE`, so fractional sizes like 0.5
+/// stay exact integers. Parses from and displays as a decimal.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Qty(i64);
+
+impl Qty {
+    pub const SCALE: i64 = 100_000_000;
+    pub const ZERO: Qty = Qty(0);
+    pub const MAX: Qty = Qty(i64::MAX);
+
+    /// `units` whole units.
+    pub const fn units(units: i64) -> Qty {
+        Qty(units * Qty::SCALE)
+    }
+
+    pub const fn from_base_units(base_units: i64) -> Qty {
+        Qty(base_units)
+    }
+
+    pub const fn base_units(self) -> i64 {
+        self.0
+    }
+
+    pub fn checked_sub(self, other: Qty) -> Option<Qty> {
+        self.0.checked_sub(other.0).map(Qty)
+    }
+
+    pub fn abs(self) -> Qty {
+        Qty(self.0.abs())
+    }
+}
+
+impl Add for Qty {
+    type Output = Qty;
+
+    fn add(self, other: Qty) -> Qty {
+        Qty(self.0 + other.0)
+    }
+}
+
+impl Sub for Qty {
+    type Output = Qty;
+
+    fn sub(self, other: Qty) -> Qty {
+        Qty(self.0 - other.0)
+    }
+}
+
+impl AddAssign for Qty {
+    fn add_assign(&mut self, other: Qty) {
+        self.0 += other.0;
+    }
+}
+
+impl SubAssign for Qty {
+    fn sub_assign(&mut self, other: Qty) {
+        self.0 -= other.0;
+    }
+}
+
+impl Sum for Qty {
+    fn sum<I: Iterator<Item = Qty>>(iter: I) -> Qty {
+        iter.fold(Qty::ZERO, Add::add)
+    }
+}
+
+impl<'a> Sum<&'a Qty> for Qty {
+    fn sum<I: Iterator<Item = &'a Qty>>(iter: I) -> Qty {
+        iter.copied().sum()
+    }
+}
+
+impl fmt::Display for Qty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_scaled(f, i128::from(self.0))
+    }
+}
+
+/// Writes `value` base units of `1 / Qty::SCALE` as an exact decimal.
+fn fmt_scaled(f: &mut fmt::Formatter, value: i128) -> fmt::Result {
+    let scale = i128::from(Qty::SCALE);
+    let sign = if value < 0 { "-" } else { "" };
+    let whole = (value / scale).unsigned_abs();
+    let fraction = (value % scale).unsigned_abs();
+    if fraction == 0 {
+        return write!(f, "{}{}", sign, whole);
+    }
+    let digits = format!("{:08}", fraction);
+    write!(f, "{}{}.{}", sign, whole, digits.trim_end_matches('0'))
+}
+
+// as the exact decimal string it displays as; a float would round
+#[cfg(feature = "serde")]
+impl serde::Serialize for Qty {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl fmt::Debug for Qty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl FromStr for Qty {
+    type Err = OrderParseError;
+
+    /// Accepts whole numbers and decimals with up to eight fractional digits.
+    fn from_str(raw_str: &str) -> Result<Self, Self::Err> {
+        parse_scaled(raw_str, Qty::SCALE).map(Qty)
+    }
+}
+
+/// Parses a decimal like "-10.25" into a whole number of `1 / scale` units,
+/// where `scale` is a power of ten. More fractional digits than `scale` allows
+/// is an error rather than a rounding.
+fn parse_scaled(raw_str: &str, scale: i64) -> Result<i64, OrderParseError> {
+    let invalid = || OrderParseError::InvalidValue(raw_str.to_string());
+    let digits = scale.to_string().len() - 1;
+    let (negative, unsigned) = match raw_str.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, raw_str),
+    };
+    let (whole, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    let all_digits = |part: &str| part.bytes().all(|byte| byte.is_ascii_digit());
+    if whole.is_empty() || fraction.len() > digits || !all_digits(whole) || !all_digits(fraction) {
+        return Err(invalid());
+    }
+    let whole: i64 = whole.parse().map_err(|_| invalid())?;
+    // padded to exactly `digits` digits, which is empty for a scale of 1
+    let fraction: i64 = format!("{:0<width$}", fraction, width = digits)
+        .parse()
+        .unwrap_or(0);
+    let scaled = whole
+        .checked_mul(scale)
+        .and_then(|scaled| scaled.checked_add(fraction))
+        .ok_or_else(invalid)?;
+    Ok(if negative { -scaled } else { scaled })
+}
+
 #[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "lowercase")
+)]
 pub enum Side {
     Bid,
     Ask,
 }
 
-#[derive(Copy, Clone, Debug)]
+impl Side {
+    /// Accepts "buy"/"b"/"bid" and "sell"/"s"/"ask" in any case, as well as the
+    /// legacy 8 (bid) and 4 (ask).
+    pub fn from_token(token: &str) -> Result<Side, OrderParseError> {
+        match token.to_ascii_lowercase().as_str() {
+            "8" | "buy" | "b" | "bid" => Ok(Side::Bid),
+            "4" | "sell" | "s" | "ask" => Ok(Side::Ask),
+            _ => Err(OrderParseError::InvalidSide(token.to_string())),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum OrderParseError {
+    InvalidSide(String),
+    InvalidValue(String),
+    UnknownField(String),
+    MissingField(&'static str),
+    WrongFieldCount { expected: usize, got: usize },
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Trade {
-    pub executing_order_id: i32,
-    pub matched_order_id: i32,
+    /// Position on the engine's tape, from 1; 0 until the trade is recorded.
+    pub sequence: u64,
+    /// The incoming order, or the bid when an auction uncrosses the book.
+    pub executing_order_id: u64,
+    /// The resting order it crossed; 0 for fills from an external venue.
+    pub matched_order_id: u64,
     pub timestamp: u128,
-    pub amount: i32,
-    pub price: i32,
+    pub amount: Qty,
+    pub price: PxTicks,
+    /// What the maker earned against the pre-trade mid, in basis points. Only
+    /// filled in when the engine records maker edge.
+    pub maker_edge_bps: Option<f64>,
+    /// Filled by an `ExternalVenue` rather than a resting order.
+    pub external: bool,
+    pub buyer_account: Option<u64>,
+    pub seller_account: Option<u64>,
+    /// Flagged by `MatchingEngine::bust_trade`; busted trades stay on the tape.
+    pub busted: bool,
+}
+
+impl Trade {
+    pub fn notional(&self) -> Notional {
+        notional(self.price, self.amount)
+    }
+}
+
+/// `price * amount` widened so that large institutional sizes can't overflow.
+/// Every place that sums notional should go through this.
+pub fn notional(price: PxTicks, amount: Qty) -> Notional {
+    Notional(i128::from(price) * i128::from(amount.base_units()))
+}
+
+/// Price times quantity in ticks times whole units, e.g. 5 units at 10 ticks is
+/// 50. Kept exact in `Qty` base units and displays as a decimal like `Qty`.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Notional(i128);
+
+impl Notional {
+    pub const ZERO: Notional = Notional(0);
+
+    /// `ticks` ticks times one whole unit.
+    pub const fn ticks(ticks: i128) -> Notional {
+        Notional(ticks * Qty::SCALE as i128)
+    }
+
+    pub const fn from_base_units(base_units: i128) -> Notional {
+        Notional(base_units)
+    }
+
+    /// Ticks times `Qty` base units, i.e. scaled by `Qty::SCALE`.
+    pub const fn base_units(self) -> i128 {
+        self.0
+    }
+
+    /// Ticks times whole units, rounded to the nearest `f64`.
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / Qty::SCALE as f64
+    }
+}
+
+impl Add for Notional {
+    type Output = Notional;
+
+    fn add(self, other: Notional) -> Notional {
+        Notional(self.0 + other.0)
+    }
+}
+
+impl Sub for Notional {
+    type Output = Notional;
+
+    fn sub(self, other: Notional) -> Notional {
+        Notional(self.0 - other.0)
+    }
+}
+
+impl AddAssign for Notional {
+    fn add_assign(&mut self, other: Notional) {
+        self.0 += other.0;
+    }
+}
+
+impl SubAssign for Notional {
+    fn sub_assign(&mut self, other: Notional) {
+        self.0 -= other.0;
+    }
+}
+
+impl Sum for Notional {
+    fn sum<I: Iterator<Item = Notional>>(iter: I) -> Notional {
+        iter.fold(Notional::ZERO, Add::add)
+    }
+}
+
+impl fmt::Display for Notional {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_scaled(f, self.0)
+    }
+}
+
+impl fmt::Debug for Notional {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Notional {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Debug)]
-enum Strategy {
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Strategy {
     Limit,
     Market,
+    // held back until a trade prints at or through `trigger`, then a market order
+    MarketIfTouched { trigger: PxTicks },
 }
 
 impl Strategy {
+    fn from_token(token: &str) -> Result<Strategy, OrderParseError> {
+        match token.to_ascii_lowercase().as_str() {
+            "limit" => Ok(Strategy::Limit),
+            "market" => Ok(Strategy::Market),
+            _ => Err(OrderParseError::InvalidValue(token.to_string())),
+        }
+    }
+
     fn matches(&self, this_order: &Order, other: &Order) -> bool {
-        if *self == Strategy::Limit {
-            return (this_order.side == Side::Bid && this_order.price >= other.price)
-                || (this_order.side == Side::Ask && this_order.price <= other.price);
-        } else if *self == Strategy::Market {
-            return true;
-        } else {
-            panic!("Noooo");
+        self.accepts_price(this_order, other.price)
+    }
+
+    fn accepts_price(&self, this_order: &Order, price: PxTicks) -> bool {
+        match self {
+            Strategy::Limit => {
+                (this_order.side == Side::Bid && this_order.price >= price)
+                    || (this_order.side == Side::Ask && this_order.price <= price)
+            }
+            Strategy::Market | Strategy::MarketIfTouched { .. } => true,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum TimeInForce {
+    Gtc,
+    /// Takes what crosses on arrival and cancels the rest.
+    Ioc,
+    /// Fills completely on arrival or not at all.
+    Fok,
+    /// Post-only with some slack: an order crossing the opposite top by at most
+    /// `tolerance` ticks of the engine's tick size is repriced one tick short of
+    /// it and rests; one crossing by more is rejected.
+    BookOrCancel {
+        tolerance: PxTicks,
+    },
+}
+
+impl TimeInForce {
+    fn from_token(token: &str) -> Result<TimeInForce, OrderParseError> {
+        match token.to_ascii_lowercase().as_str() {
+            "gtc" => Ok(TimeInForce::Gtc),
+            "ioc" => Ok(TimeInForce::Ioc),
+            "fok" => Ok(TimeInForce::Fok),
+            _ => Err(OrderParseError::InvalidValue(token.to_string())),
         }
     }
+
+    /// Takes the liquidity that crosses on arrival and rests the remainder as a
+    /// maker. The remainder never crosses again on the same submit because the
+    /// opposite side is only walked once, before the order is rested.
+    pub const TAKE_THEN_REST: TimeInForce = TimeInForce::Gtc;
 }
 
 #[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Order {
+    // 0 until the engine assigns one
+    pub id: u64,
     pub side: Side,
-    pub amount: i32,
-    pub price: i32,
+    // what is left to trade; `filled` is what already has
+    pub amount: Qty,
+    pub filled: Qty,
+    pub price: PxTicks,
     pub timestamp: i32,
+    pub time_in_force: TimeInForce,
+    // cancel the resting order once this many trades have happened on the engine
+    pub cancel_after_trades: Option<u64>,
+    pub(crate) expires_at_trade: Option<u64>,
+    // engine time before which the order is held back instead of matching
+    pub not_before: Option<u128>,
+    pub account: Option<u64>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) seq: u64,
+    // engine time the order went live, for the engine's `min_rest`
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) live_since: u128,
     strategy: Strategy,
 }
 
 impl Order {
+    pub fn limit(side: Side, amount: Qty, price: PxTicks, timestamp: i32) -> Self {
+        Self {
+            id: 0,
+            side,
+            amount,
+            filled: Qty::ZERO,
+            price,
+            timestamp,
+            time_in_force: TimeInForce::Gtc,
+            cancel_after_trades: None,
+            expires_at_trade: None,
+            not_before: None,
+            account: None,
+            seq: 0,
+            live_since: 0,
+            strategy: Strategy::Limit,
+        }
+    }
+
+    /// A buy that waits for the price to fall to `trigger` (a sell, to rise to
+    /// it) and then executes as a market order.
+    pub fn market_if_touched(side: Side, amount: Qty, trigger: PxTicks, timestamp: i32) -> Self {
+        Self {
+            strategy: Strategy::MarketIfTouched { trigger },
+            ..Self::limit(side, amount, 0, timestamp)
+        }
+    }
+
+    pub fn strategy(&self) -> Strategy {
+        self.strategy
+    }
+
     pub fn matches(&self, other: &Self) -> bool {
-        return self.strategy.matches(self, other);
+        self.strategy.matches(self, other)
+    }
+
+    /// Whether this order would trade at `price`, e.g. an auction clearing price.
+    pub fn accepts_price(&self, price: PxTicks) -> bool {
+        self.strategy.accepts_price(self, price)
+    }
+
+    /// True for market orders, including market-if-touched ones.
+    pub fn is_market(&self) -> bool {
+        self.strategy != Strategy::Limit
+    }
+
+    pub(crate) fn is_triggered(&self) -> bool {
+        !matches!(self.strategy, Strategy::MarketIfTouched { .. })
+    }
+
+    /// Whether trades printing between `low` and `high` touch the trigger.
+    pub(crate) fn touched(&self, low: PxTicks, high: PxTicks) -> bool {
+        match (self.strategy, self.side) {
+            (Strategy::MarketIfTouched { trigger }, Side::Bid) => low <= trigger,
+            (Strategy::MarketIfTouched { trigger }, Side::Ask) => high >= trigger,
+            _ => false,
+        }
+    }
+
+    /// Turns a touched market-if-touched order into the market order it becomes.
+    pub(crate) fn trigger(mut self) -> Self {
+        self.strategy = Strategy::Market;
+        self
+    }
+
+    /// Turns a market order into a limit order at `price`.
+    pub(crate) fn into_limit(mut self, price: PxTicks) -> Self {
+        self.strategy = Strategy::Limit;
+        self.price = price;
+        self
     }
 }
 
 impl Ord for Order {
     fn cmp(&self, other: &Self) -> Ordering {
-        let multiplier;
-        if self.side == Side::Ask {
-            multiplier = -1;
+        // BinaryHeap pops the greatest order first: the highest bid or the lowest ask,
+        // and the earliest timestamp (then the earliest seq) within a price level.
+        // `into_sorted_vec` is ascending, so the best order comes last
+        let price_order = if self.side == Side::Ask {
+            other.price.cmp(&self.price)
         } else {
-            multiplier = 1;
-        }
-        (self.price, self.timestamp).cmp(&((other.price * multiplier), other.timestamp))
+            self.price.cmp(&other.price)
+        };
+        // side and amount never decide priority; they only keep `cmp` in line
+        // with `eq`
+        price_order
+            .then_with(|| other.timestamp.cmp(&self.timestamp))
+            .then_with(|| other.seq.cmp(&self.seq))
+            .then_with(|| (self.side == Side::Bid).cmp(&(other.side == Side::Bid)))
+            .then_with(|| self.amount.cmp(&other.amount))
     }
 }
 
@@ -70,47 +496,149 @@ impl PartialOrd for Order {
 
 impl PartialEq for Order {
     fn eq(&self, other: &Self) -> bool {
-        self.price == other.price && self.timestamp == other.timestamp
+        self.side == other.side
+            && self.amount == other.amount
+            && self.price == other.price
+            && self.timestamp == other.timestamp
+            && self.seq == other.seq
     }
 }
 
 impl Eq for Order {}
 
 impl FromStr for Order {
-    type Err = std::num::ParseIntError;
+    type Err = OrderParseError;
 
     fn from_str(raw_str: &str) -> Result<Self, Self::Err> {
-        let (side_int, amount, price, timestamp, strategy_int): (i32, i32, i32, i32, i32);
-        scan!(raw_str.bytes() => "{} {} {} {} {}", side_int, amount, price, timestamp, strategy_int);
-        let side;
-        if side_int == 4 {
-            side = Side::Ask;
-        } else if side_int == 8 {
-            side = Side::Bid;
-        } else {
-            panic!("Invalid side");
-        }
-        let strategy;
-        if strategy_int == 0 {
-            strategy = Strategy::Limit;
-        } else if strategy_int == 1 {
-            strategy = Strategy::Market;
-        } else {
-            panic!("Invalid strategy");
+        order_from_str_with_price_scale(raw_str, 1)
+    }
+}
+
+/// side, amount, price, timestamp, strategy; optionally preceded by an order id
+/// and followed by a time in force
+const POSITIONAL_FIELDS: usize = 5;
+
+pub fn order_from_str(raw_str: &str) -> Result<Order, <Order as FromStr>::Err> {
+    Order::from_str(raw_str)
+}
+
+/// Like `order_from_str`, but prices may be decimals with as many fractional
+/// digits as `price_scale` (a power of ten) allows, stored as `price * price_scale`
+/// ticks. With a scale of 100, "10.25" becomes 1025.
+pub fn order_from_str_with_price_scale(
+    raw_str: &str,
+    price_scale: i64,
+) -> Result<Order, OrderParseError> {
+    if raw_str.contains('=') {
+        return order_from_named_fields(raw_str, price_scale);
+    }
+    let mut fields: Vec<&str> = raw_str.split_whitespace().collect();
+    let got = fields.len();
+    // the time in force is the only field that isn't a number
+    let time_in_force = match fields.last() {
+        Some(token) if got > POSITIONAL_FIELDS && token.parse::<f64>().is_err() => {
+            let time_in_force = TimeInForce::from_token(token)?;
+            fields.pop();
+            time_in_force
         }
+        _ => TimeInForce::Gtc,
+    };
+    let has_tif = fields.len() < got;
+    if fields.len() < POSITIONAL_FIELDS {
+        return Err(OrderParseError::WrongFieldCount {
+            expected: POSITIONAL_FIELDS + has_tif as usize,
+            got,
+        });
+    }
+    if fields.len() > POSITIONAL_FIELDS + 1 {
+        return Err(OrderParseError::WrongFieldCount {
+            expected: POSITIONAL_FIELDS + 1 + has_tif as usize,
+            got,
+        });
+    }
+    let id = if fields.len() > POSITIONAL_FIELDS {
+        let token = fields.remove(0);
+        token
+            .parse()
+            .map_err(|_| OrderParseError::InvalidValue(token.to_string()))?
+    } else {
+        0
+    };
+    let side = Side::from_token(fields[0])?;
+    let amount: Qty = parse_value(fields[1])?;
+    let price = parse_scaled(fields[2], price_scale)?;
+    let timestamp: i32 = parse_value(fields[3])?;
+    let strategy = match fields[4] {
+        "0" => Strategy::Limit,
+        "1" => Strategy::Market,
+        token => return Err(OrderParseError::InvalidValue(token.to_string())),
+    };
+
+    Ok(Order {
+        id,
+        side,
+        amount,
+        filled: Qty::ZERO,
+        price,
+        timestamp,
+        time_in_force,
+        cancel_after_trades: None,
+        expires_at_trade: None,
+        not_before: None,
+        account: None,
+        seq: 0,
+        live_since: 0,
+        strategy,
+    })
+}
 
-        Ok(Self {
-            side: side,
-            amount: amount,
-            price: price,
-            timestamp: timestamp,
-            strategy: strategy,
+/// Splits `key=value` tokens on whitespace.
+fn tokenize(raw_str: &str) -> Result<Vec<(&str, &str)>, OrderParseError> {
+    raw_str
+        .split_whitespace()
+        .map(|token| {
+            token
+                .split_once('=')
+                .ok_or_else(|| OrderParseError::InvalidValue(token.to_string()))
         })
-    }
+        .collect()
 }
 
-pub fn order_from_str(raw_str: &str) -> Result<Order, <Order as FromStr>::Err> {
-    return Order::from_str(raw_str);
+fn parse_value<T: FromStr>(value: &str) -> Result<T, OrderParseError> {
+    value
+        .parse()
+        .map_err(|_| OrderParseError::InvalidValue(value.to_string()))
+}
+
+/// Parses e.g. `side=buy qty=10 px=100 tif=gtc type=limit`, fields in any order.
+/// `side` and `qty` are required, `px` only for limit orders; `type` defaults to
+/// limit, `tif` to gtc and `ts`/`id` to 0; `account` is optional.
+fn order_from_named_fields(raw_str: &str, price_scale: i64) -> Result<Order, OrderParseError> {
+    let mut side = None;
+    let mut amount = None;
+    let mut price = None;
+    let mut order = Order::limit(Side::Bid, Qty::ZERO, 0, 0);
+    for (key, value) in tokenize(raw_str)? {
+        match key.to_ascii_lowercase().as_str() {
+            "side" => side = Some(Side::from_token(value)?),
+            "qty" | "amount" => amount = Some(parse_value(value)?),
+            "px" | "price" => price = Some(parse_scaled(value, price_scale)?),
+            "ts" | "timestamp" => order.timestamp = parse_value(value)?,
+            "id" => order.id = parse_value(value)?,
+            "account" => order.account = Some(parse_value(value)?),
+            "type" => order.strategy = Strategy::from_token(value)?,
+            "tif" => order.time_in_force = TimeInForce::from_token(value)?,
+            _ => return Err(OrderParseError::UnknownField(key.to_string())),
+        }
+    }
+    order.side = side.ok_or(OrderParseError::MissingField("side"))?;
+    order.amount = amount.ok_or(OrderParseError::MissingField("qty"))?;
+    order.price = match (price, order.strategy) {
+        (Some(price), _) => price,
+        (None, Strategy::Market) | (None, Strategy::MarketIfTouched { .. }) => 0,
+        (None, Strategy::Limit) => return Err(OrderParseError::MissingField("px")),
+    };
+    Ok(order)
 }
 
 #[cfg(test)]
@@ -122,10 +650,19 @@ mod tests {
         assert_eq!(
             order_from_str("8 1 2 0 0"),
             Ok(Order {
+                id: 0,
                 side: Side::Bid,
-                amount: 1,
+                amount: Qty::units(1),
+                filled: Qty::ZERO,
                 price: 2,
                 timestamp: 0,
+                time_in_force: TimeInForce::Gtc,
+                cancel_after_trades: None,
+                expires_at_trade: None,
+                not_before: None,
+                account: None,
+                seq: 0,
+                live_since: 0,
                 strategy: Strategy::Limit
             })
         );
@@ -136,19 +673,259 @@ mod tests {
         assert_eq!(
             order_from_str("4 9 1 2 0"),
             Ok(Order {
-                side: Side::Bid,
-                amount: 9,
+                id: 0,
+                side: Side::Ask,
+                amount: Qty::units(9),
+                filled: Qty::ZERO,
                 price: 1,
                 timestamp: 2,
+                time_in_force: TimeInForce::Gtc,
+                cancel_after_trades: None,
+                expires_at_trade: None,
+                not_before: None,
+                account: None,
+                seq: 0,
+                live_since: 0,
                 strategy: Strategy::Limit
             })
         );
     }
 
+    #[test]
+    fn test_orders_differing_in_side_or_amount_are_not_equal() {
+        let bid = order_from_str("8 1 2 0 0").unwrap();
+
+        assert_ne!(bid, order_from_str("4 1 2 0 0").unwrap());
+        assert_ne!(bid, order_from_str("8 3 2 0 0").unwrap());
+        assert_ne!(
+            bid.cmp(&order_from_str("8 3 2 0 0").unwrap()),
+            Ordering::Equal
+        );
+        assert_eq!(bid, order_from_str("8 1 2 0 0").unwrap());
+    }
+
+    #[test]
+    fn test_side_from_token() {
+        for token in ["8", "BUY", "buy", "B", "b", "bid", "BID"].iter() {
+            assert_eq!(Side::from_token(token), Ok(Side::Bid));
+        }
+        for token in ["4", "SELL", "sell", "S", "s", "ask", "ASK"].iter() {
+            assert_eq!(Side::from_token(token), Ok(Side::Ask));
+        }
+    }
+
+    #[test]
+    fn test_side_from_invalid_token() {
+        assert_eq!(
+            Side::from_token("X"),
+            Err(OrderParseError::InvalidSide("X".to_string()))
+        );
+        assert_eq!(
+            order_from_str("5 1 2 0 0"),
+            Err(OrderParseError::InvalidSide("5".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_wrong_field_count_rejected() {
+        assert_eq!(
+            order_from_str("8 1"),
+            Err(OrderParseError::WrongFieldCount {
+                expected: 5,
+                got: 2
+            })
+        );
+        assert_eq!(
+            order_from_str("8 1 2 0 0 ioc 7"),
+            Err(OrderParseError::WrongFieldCount {
+                expected: 6,
+                got: 7
+            })
+        );
+    }
+
+    #[test]
+    fn test_invalid_positional_fields_rejected() {
+        let invalid = |token: &str| Err(OrderParseError::InvalidValue(token.to_string()));
+        assert_eq!(order_from_str("8 1 2 0 2"), invalid("2"));
+        assert_eq!(order_from_str("8 x 2 0 0"), invalid("x"));
+        assert_eq!(order_from_str("8 1 2 t 0"), invalid("t"));
+        assert_eq!(order_from_str("8 1 2 0 x"), invalid("x"));
+        assert_eq!(
+            order_from_str("7 1 2 0 0"),
+            Err(OrderParseError::InvalidSide("7".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_optional_leading_id() {
+        let order = order_from_str("42 8 1 2 0 0").unwrap();
+        assert_eq!((order.id, order.side, order.price), (42, Side::Bid, 2));
+        let order = order_from_str("42 4 1 2 0 0 ioc").unwrap();
+        assert_eq!((order.id, order.time_in_force), (42, TimeInForce::Ioc));
+        assert_eq!(order_from_str("8 1 2 0 0").unwrap().id, 0);
+        assert_eq!(
+            order_from_str("x 8 1 2 0 0"),
+            Err(OrderParseError::InvalidValue("x".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_time_in_force_sixth_field() {
+        assert_eq!(
+            order_from_str("8 1 2 0 0 IOC").unwrap().time_in_force,
+            TimeInForce::Ioc
+        );
+        assert_eq!(
+            order_from_str("8 1 2 0 0 fok").unwrap().time_in_force,
+            TimeInForce::Fok
+        );
+        assert_eq!(
+            order_from_str("8 1 2 0 0").unwrap().time_in_force,
+            TimeInForce::Gtc
+        );
+        assert_eq!(
+            order_from_str("8 1 2 0 0 day"),
+            Err(OrderParseError::InvalidValue("day".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_order_from_str_symbolic_side() {
+        assert_eq!(order_from_str("BUY 1 2 0 0").unwrap().side, Side::Bid);
+        assert_eq!(order_from_str("S 1 2 0 0").unwrap().side, Side::Ask);
+    }
+
+    #[test]
+    fn test_order_from_named_fields_in_any_order() {
+        let order = order_from_str("px=100 tif=gtc qty=10 type=limit side=buy ts=3 id=7").unwrap();
+
+        assert_eq!(order.side, Side::Bid);
+        assert_eq!(order.amount, Qty::units(10));
+        assert_eq!(order.price, 100);
+        assert_eq!(order.timestamp, 3);
+        assert_eq!(order.id, 7);
+        assert_eq!(order.time_in_force, TimeInForce::Gtc);
+        assert!(!order.is_market());
+    }
+
+    #[test]
+    fn test_order_from_named_fields_defaults() {
+        let order = order_from_str("qty=5 side=sell type=market").unwrap();
+
+        assert_eq!(order.side, Side::Ask);
+        assert_eq!(order.amount, Qty::units(5));
+        assert_eq!(order.price, 0);
+        assert_eq!(order.timestamp, 0);
+        assert_eq!(order.id, 0);
+        assert_eq!(order.time_in_force, TimeInForce::Gtc);
+        assert!(order.is_market());
+    }
+
+    #[test]
+    fn test_order_from_named_fields_errors() {
+        assert_eq!(
+            order_from_str("side=buy qty=10"),
+            Err(OrderParseError::MissingField("px"))
+        );
+        assert_eq!(
+            order_from_str("side=buy qty=ten px=1"),
+            Err(OrderParseError::InvalidValue("ten".to_string()))
+        );
+        assert_eq!(
+            order_from_str("side=buy qty=1 px=1 colour=red"),
+            Err(OrderParseError::UnknownField("colour".to_string()))
+        );
+        assert_eq!(
+            order_from_str("side=buy qty=1 px"),
+            Err(OrderParseError::InvalidValue("px".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_notional_does_not_overflow() {
+        assert_eq!(
+            notional(1_000_000, Qty::units(1_000_000)),
+            Notional::ticks(1_000_000_000_000)
+        );
+        assert_eq!(
+            notional(i64::MAX, Qty::MAX).base_units(),
+            i128::from(i64::MAX) * i128::from(i64::MAX)
+        );
+    }
+
+    #[test]
+    fn test_notional_is_in_whole_units() {
+        assert_eq!(notional(10, Qty::units(5)).to_string(), "50");
+        assert_eq!(notional(3, "0.5".parse().unwrap()).to_string(), "1.5");
+        assert_eq!(notional(-7, "0.25".parse().unwrap()).to_string(), "-1.75");
+        assert_eq!(notional(10, Qty::units(5)).to_f64(), 50.0);
+    }
+
+    #[test]
+    fn test_qty_parses_and_displays_decimals() {
+        assert_eq!("10".parse(), Ok(Qty::units(10)));
+        assert_eq!("0.5".parse(), Ok(Qty::from_base_units(50_000_000)));
+        assert_eq!("0.00000001".parse(), Ok(Qty::from_base_units(1)));
+        assert_eq!("-1.25".parse(), Ok(Qty::from_base_units(-125_000_000)));
+        assert!("0.000000001".parse::<Qty>().is_err());
+        assert!("1.".parse::<Qty>().is_ok());
+        assert!(".5".parse::<Qty>().is_err());
+        assert!("1e3".parse::<Qty>().is_err());
+        assert!("99999999999999999999".parse::<Qty>().is_err());
+        assert_eq!(Qty::from_base_units(20_000_000).to_string(), "0.2");
+        assert_eq!(Qty::units(12).to_string(), "12");
+        assert_eq!(format!("{:?}", Qty::from_base_units(-1)), "-0.00000001");
+    }
+
+    #[test]
+    fn test_decimal_price_scaled_to_ticks() {
+        let order = order_from_str_with_price_scale("8 1 10.25 0 0", 100).unwrap();
+        assert_eq!(order.price, 1025);
+        let named = order_from_str_with_price_scale("side=buy qty=1 px=10.5", 100).unwrap();
+        assert_eq!(named.price, 1050);
+        assert_eq!(
+            order_from_str_with_price_scale("8 1 10.255 0 0", 100),
+            Err(OrderParseError::InvalidValue("10.255".to_string()))
+        );
+        assert!(order_from_str("8 1 10.25 0 0").is_err());
+        assert_eq!(order_from_str("8 1 10 0 0").unwrap().price, 10);
+    }
+
     #[test]
     fn test_order_matches() {
         let executing_order = order_from_str("8 1 1 5 0").unwrap();
         let other_order = order_from_str("4 1 1 3 0").unwrap();
         assert!(executing_order.matches(&other_order));
     }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serialize_trade_and_side() {
+        let trade = Trade {
+            sequence: 1,
+            executing_order_id: 2,
+            matched_order_id: 1,
+            timestamp: 7,
+            amount: Qty::from_base_units(150_000_000),
+            price: 10,
+            maker_edge_bps: None,
+            external: false,
+            buyer_account: None,
+            seller_account: None,
+            busted: false,
+        };
+
+        assert_eq!(
+            serde_json::to_string(&trade).unwrap(),
+            concat!(
+                "{\"sequence\":1,\"executing_order_id\":2,\"matched_order_id\":1,",
+                "\"timestamp\":7,\"amount\":\"1.5\",\"price\":10,\"maker_edge_bps\":null,",
+                "\"external\":false,\"buyer_account\":null,\"seller_account\":null,",
+                "\"busted\":false}"
+            )
+        );
+        assert_eq!(serde_json::to_string(&Side::Bid).unwrap(), "\"bid\"");
+        assert_eq!(serde_json::to_string(&Side::Ask).unwrap(), "\"ask\"");
+    }
 }
@@ -0,0 +1,84 @@
+use std::collections::BTreeMap;
+
+use crate::engine::PriceLevel;
+use crate::orders::{PxTicks, Qty, Side};
+
+/// Aggregated levels of both sides at one point in time, best first.
+#[derive(Clone, PartialEq, Debug)]
+pub struct BookSnapshot {
+    pub bids: Vec<PriceLevel>,
+    pub asks: Vec<PriceLevel>,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum LevelChange {
+    Added {
+        side: Side,
+        price: PxTicks,
+        amount: Qty,
+    },
+    Removed {
+        side: Side,
+        price: PxTicks,
+        amount: Qty,
+    },
+    Changed {
+        side: Side,
+        price: PxTicks,
+        from: Qty,
+        to: Qty,
+    },
+}
+
+/// Level changes between two snapshots, bids before asks and by ascending price.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct BookDiff {
+    pub changes: Vec<LevelChange>,
+}
+
+impl BookDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl BookSnapshot {
+    /// What changed going from `self` to `other`.
+    pub fn diff(&self, other: &BookSnapshot) -> BookDiff {
+        let mut changes = Vec::new();
+        diff_side(Side::Bid, &self.bids, &other.bids, &mut changes);
+        diff_side(Side::Ask, &self.asks, &other.asks, &mut changes);
+        BookDiff { changes }
+    }
+}
+
+fn diff_side(side: Side, before: &[PriceLevel], after: &[PriceLevel], out: &mut Vec<LevelChange>) {
+    let mut levels = BTreeMap::<PxTicks, (Qty, Qty)>::new();
+    for &(price, amount) in before {
+        levels.entry(price).or_default().0 = amount;
+    }
+    for &(price, amount) in after {
+        levels.entry(price).or_default().1 = amount;
+    }
+    for (price, (from, to)) in levels {
+        match (from, to) {
+            (from, to) if from == to => {}
+            (Qty::ZERO, amount) => out.push(LevelChange::Added {
+                side,
+                price,
+                amount,
+            }),
+            (amount, Qty::ZERO) => out.push(LevelChange::Removed {
+                side,
+                price,
+                amount,
+            }),
+            (from, to) => out.push(LevelChange::Changed {
+                side,
+                price,
+                from,
+                to,
+            }),
+        }
+    }
+}
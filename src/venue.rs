@@ -0,0 +1,9 @@
+use crate::orders::{PxTicks, Qty, Side};
+
+/// Outside liquidity the engine falls back to once its own book can't fill an
+/// order, e.g. for smart order routing experiments.
+pub trait ExternalVenue: Send {
+    /// Price and the quantity (at most `qty`) the venue would fill for an order
+    /// on `side`, or `None` if it has nothing.
+    fn quote(&self, side: Side, qty: Qty) -> Option<(PxTicks, Qty)>;
+}
@@ -0,0 +1,57 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+#[test]
+fn test_piped_script_prints_final_book_on_eof() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rusty_matching"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"4 10 12 1 0\n\n8 4 12 2 0\n8 5 9 3 0\n")
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(!stdout.contains("Couldn't parse input"));
+    assert!(stdout.ends_with("Final book\nBids: [(9, 5)]\nAsks: [(12, 6)]\n"));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_json_flag_prints_trades_and_book_as_json() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rusty_matching"))
+        .arg("--json")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"4 10 12 1 0\nnot an order\n8 4 12 2 0\n")
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0], "[]");
+    assert!(
+        lines[1].starts_with("[{\"sequence\":1,\"executing_order_id\":2,\"matched_order_id\":1,")
+    );
+    assert!(lines[1].contains("\"amount\":\"4\",\"price\":12,"));
+    assert!(lines[2].starts_with("{\"bids\":[],\"asks\":[{\"price\":12,\"amount\":\"6\","));
+    assert_eq!(stderr, "Couldn't parse input: 'not an order'\n");
+}